@@ -1,5 +1,12 @@
-use crate::config::{Config, PendingFile};
+use crate::config::{Config, PendingFile, WatchEntry};
+use crate::events::WatcherEvent;
+use crate::file_organizer::Organized;
 use crate::file_watcher::FileWatcher;
+use crate::history::{self, HistoryAction, HistoryEntry, HistoryOutcome, HistoryPage, HistoryQuery};
+use crate::preview::{self, PreviewReport};
+use crate::routing::{classify_and_route, RoutingDecision};
+use crate::settle::SettlingFile;
+use crate::undo::{self, RevertReport};
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use tokio::sync::broadcast;
@@ -11,9 +18,13 @@ static WATCHER: Mutex<Option<Arc<Mutex<FileWatcher>>>> = Mutex::new(None);
 
 static MODAL_SHOWING: Mutex<bool> = Mutex::new(false);
 
-pub fn init_watcher() -> Result<broadcast::Sender<String>, String> {
+pub fn init_watcher() -> Result<broadcast::Sender<WatcherEvent>, String> {
     let (tx, _) = broadcast::channel(100);
-    let watcher = FileWatcher::new(tx.clone())?;
+    let mut watcher = FileWatcher::new(tx.clone())?;
+    // Folders that fail to watch are reported but don't stop the app from starting
+    if let Err(e) = watcher.sync_with_config() {
+        let _ = tx.send(WatcherEvent::watch_error(e));
+    }
     let watcher_arc = Arc::new(Mutex::new(watcher));
     *WATCHER.lock().unwrap() = Some(watcher_arc);
     Ok(tx)
@@ -26,11 +37,16 @@ pub fn get_config() -> Result<Config, String> {
 
 #[tauri::command]
 pub fn save_config(app: tauri::AppHandle, config: Config) -> Result<(), String> {
+    let mut config = config;
+    config.migrate_watched_folder();
+    config.normalize_watched_folders();
+    config.validate()?;
     config.save()?;
 
     if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
-        let watcher = watcher_arc.lock().unwrap();
+        let mut watcher = watcher_arc.lock().unwrap();
         watcher.update_config(config.clone())?;
+        watcher.sync_with_config()?;
     }
 
     // Apply settings immediately
@@ -142,6 +158,7 @@ fn set_launch_at_login(_enabled: bool) -> Result<(), String> {
     Ok(())
 }
 
+/// Watches one folder, adding it to the config (or re-enabling its entry) as needed.
 #[tauri::command]
 pub fn start_watching(watched_folder: String) -> Result<(), String> {
     let path = Path::new(&watched_folder);
@@ -150,6 +167,20 @@ pub fn start_watching(watched_folder: String) -> Result<(), String> {
             .map_err(|e| format!("Failed to create watched folder: {}", e))?;
     }
 
+    let mut config = Config::load();
+    let recursive = config.watch_entry(path).is_some_and(|entry| entry.recursive);
+    match config.watch_entry_mut(path) {
+        Some(entry) if entry.enabled => {}
+        Some(entry) => {
+            entry.enabled = true;
+            config.save()?;
+        }
+        None => {
+            config.watched_folders.push(WatchEntry::new(watched_folder.clone()));
+            config.save()?;
+        }
+    }
+
     let mut watcher_guard = WATCHER.lock().unwrap();
     if watcher_guard.is_none() {
         // Reinitialize if it was destroyed
@@ -160,18 +191,87 @@ pub fn start_watching(watched_folder: String) -> Result<(), String> {
 
     if let Some(watcher_arc) = watcher_guard.as_ref() {
         let mut watcher = watcher_arc.lock().unwrap();
-        watcher.watch(path)?;
+        watcher.update_config(config.clone())?;
+        watcher.watch(path, recursive)?;
+        if config.sweep_on_start && !watcher.is_sweeping() {
+            watcher.sweep(Some(path))?;
+        }
         Ok(())
     } else {
         Err("Watcher not initialized".to_string())
     }
 }
 
+/// Stops watching one folder and disables its entry, or pauses every folder
+/// (leaving the config alone) when no folder is given.
+#[tauri::command]
+pub fn stop_watching(watched_folder: Option<String>) -> Result<(), String> {
+    let Some(watched_folder) = watched_folder else {
+        if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
+            watcher_arc.lock().unwrap().unwatch_all()?;
+        }
+        return Ok(());
+    };
+
+    let path = Path::new(&watched_folder);
+    let mut config = Config::load();
+    if let Some(entry) = config.watch_entry_mut(path) {
+        entry.enabled = false;
+        config.save()?;
+    }
+    if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
+        let mut watcher = watcher_arc.lock().unwrap();
+        watcher.update_config(config)?;
+        watcher.unwatch(path)?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
-pub fn stop_watching() -> Result<(), String> {
+pub fn remove_watched_folder(watched_folder: String) -> Result<(), String> {
+    let path = Path::new(&watched_folder);
+    let mut config = Config::load();
+    config.watched_folders.retain(|entry| !entry.is_for(path));
+    config.save()?;
     if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
         let mut watcher = watcher_arc.lock().unwrap();
-        watcher.unwatch_current()?;
+        watcher.update_config(config)?;
+        watcher.unwatch(path)?;
+    }
+    Ok(())
+}
+
+/// Folders currently being watched, which can differ from the enabled entries
+/// when a folder failed to watch.
+#[tauri::command]
+pub fn get_watched_folders() -> Result<Vec<String>, String> {
+    match WATCHER.lock().unwrap().as_ref() {
+        Some(watcher_arc) => Ok(watcher_arc
+            .lock()
+            .unwrap()
+            .watched_folders()
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect()),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[tauri::command]
+pub fn sweep_watched_folder(watched_folder: Option<String>) -> Result<(), String> {
+    match WATCHER.lock().unwrap().as_ref() {
+        Some(watcher_arc) => watcher_arc
+            .lock()
+            .unwrap()
+            .sweep(watched_folder.as_deref().map(Path::new)),
+        None => Err("Watcher not initialized".to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn cancel_sweep() -> Result<(), String> {
+    if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
+        watcher_arc.lock().unwrap().cancel_sweep();
     }
     Ok(())
 }
@@ -210,6 +310,15 @@ pub fn get_pending_files() -> Result<Vec<PendingFile>, String> {
     }
 }
 
+/// Files that have appeared but are still being written.
+#[tauri::command]
+pub fn get_settling_files() -> Result<Vec<SettlingFile>, String> {
+    match WATCHER.lock().unwrap().as_ref() {
+        Some(watcher_arc) => Ok(watcher_arc.lock().unwrap().get_settling_files()),
+        None => Ok(Vec::new()),
+    }
+}
+
 #[tauri::command]
 pub fn process_pending_file(app: tauri::AppHandle, filePath: String, destination: Option<String>, newName: Option<String>) -> Result<(), String> {
     if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
@@ -234,6 +343,29 @@ pub fn process_pending_file(app: tauri::AppHandle, filePath: String, destination
     Ok(())
 }
 
+/// One-click alternative to `process_pending_file`: `index` picks one of the
+/// file's `suggestions`.
+#[tauri::command]
+pub fn accept_suggestion(app: tauri::AppHandle, filePath: String, index: usize) -> Result<(), String> {
+    if let Some(watcher_arc) = WATCHER.lock().unwrap().as_ref() {
+        let watcher = watcher_arc.lock().unwrap();
+        watcher.accept_suggestion(&filePath, index)?;
+        
+        if let Some(window) = app.get_webview_window("file-organization") {
+            let js_code = "if (window.refreshFileList) { window.refreshFileList(); }";
+            let _ = window.eval(js_code);
+        }
+        
+        if watcher.get_pending_files().is_empty() {
+            *MODAL_SHOWING.lock().unwrap() = false;
+            let _ = close_file_organization_modal(app);
+        }
+    } else {
+        return Err("Watcher not initialized".to_string());
+    }
+    Ok(())
+}
+
 #[tauri::command]
 pub fn delete_pending_file(app: tauri::AppHandle, filePath: String) -> Result<(), String> {
     use std::fs;
@@ -243,8 +375,15 @@ pub fn delete_pending_file(app: tauri::AppHandle, filePath: String) -> Result<()
     
     // Delete the file
     if path.exists() {
-        fs::remove_file(&path)
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
+        let mut entry = HistoryEntry::new(HistoryAction::Deleted, filePath.as_str());
+        entry.size = path.metadata().ok().map(|m| m.len());
+        let result = fs::remove_file(&path)
+            .map_err(|e| format!("Failed to delete file: {}", e));
+        if let Err(e) = &result {
+            entry.outcome = HistoryOutcome::Failed { message: e.clone() };
+        }
+        history::record(entry);
+        result?;
     }
     
     // Remove from pending files
@@ -270,8 +409,8 @@ pub fn delete_pending_file(app: tauri::AppHandle, filePath: String) -> Result<()
 }
 
 #[tauri::command]
-pub fn move_file_manual(file_path: String, destination: String) -> Result<String, String> {
-    use crate::file_organizer::organize_file_to_destination;
+pub fn move_file_manual(file_path: String, destination: String) -> Result<Organized, String> {
+    use crate::file_organizer::{organize_file, organize_file_to_destination, OrganizeOptions};
     use std::path::PathBuf;
     
     let path = PathBuf::from(&file_path);
@@ -279,7 +418,48 @@ pub fn move_file_manual(file_path: String, destination: String) -> Result<String
         return Err("File does not exist".to_string());
     }
 
-        organize_file_to_destination(&path, &destination, None)
+    // Without an explicit destination, let the rules of the file's folder decide
+    if destination.is_empty() {
+        return organize_file(&path, &config_for_file(&path));
+    }
+    organize_file_to_destination(&path, &destination, &OrganizeOptions::default(), None, None)
+}
+
+#[tauri::command]
+pub fn get_routing_decision(file_path: String) -> Result<RoutingDecision, String> {
+    let path = Path::new(&file_path);
+    if !path.is_file() {
+        return Err("File does not exist".to_string());
+    }
+    Ok(classify_and_route(path, &config_for_file(path)))
+}
+
+fn config_for_file(path: &Path) -> Config {
+    Config::load().for_file(path)
+}
+
+#[tauri::command]
+pub fn get_history(query: Option<HistoryQuery>) -> Result<HistoryPage, String> {
+    Ok(history::query(&query.unwrap_or_default()))
+}
+
+/// Dry run of a rule set against a folder. Uses the saved config unless one is
+/// passed, with the folder's own rules if it has a watch entry.
+#[tauri::command]
+pub fn preview_rules(config: Option<Config>, folder: String) -> Result<PreviewReport, String> {
+    let folder = Path::new(&folder);
+    let config = config.unwrap_or_else(Config::load).for_folder(folder);
+    preview::preview_rules(&config, folder)
+}
+
+#[tauri::command]
+pub fn undo_last_move() -> Result<RevertReport, String> {
+    undo::undo_last_move()
+}
+
+#[tauri::command]
+pub fn revert_moves(minutes: Option<u64>, rule: Option<String>) -> Result<RevertReport, String> {
+    undo::revert_moves(minutes, rule.as_deref())
 }
 
 #[tauri::command]
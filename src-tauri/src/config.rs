@@ -1,6 +1,16 @@
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, TimeZone};
+use crate::file_type::{self, FileKind};
+use crate::patterns::{compile_glob, glob_matches};
+use crate::routing::RuleMatch;
+use crate::suggestions::Suggestion;
+use crate::templates::{
+    destination_root, expand_destination, expand_file_name, validate_destination, validate_file_name,
+    TemplateContext,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use regex::Regex;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -10,11 +20,82 @@ pub enum RuleCondition {
     FileType { value: String },
     #[serde(rename = "name")]
     Name { pattern: String },
+    #[serde(rename = "glob")]
+    Glob { pattern: String }, // Whole-name glob with `*`, `?`, `[abc]` and `**`
+    #[serde(rename = "regex")]
+    Regex { pattern: String }, // Named groups can be used in the destination, e.g. `{vendor}`
+    #[serde(rename = "path")]
+    Path { pattern: String }, // Glob on the path below the watched folder, e.g. "scans/**/*.pdf"
     #[serde(rename = "created_date")]
     CreatedDate { 
         operator: String, // "before", "after", "on"
         value: String // ISO date string or relative date
     },
+    #[serde(rename = "size")]
+    Size {
+        operator: String, // "greater_than", "less_than", "between"
+        value: String, // Human size such as "500MB" or "2 GiB"; the lower bound for "between"
+        #[serde(default)]
+        max: Option<String>, // Upper bound for "between"
+    },
+    #[serde(rename = "mime_type")]
+    MimeType { value: String }, // e.g. "application/pdf" or "image/*"
+    #[serde(rename = "kind")]
+    Kind { value: String }, // "image", "archive", "document", "audio" or "video"
+    #[serde(rename = "all")]
+    All { conditions: Vec<RuleCondition> },
+    #[serde(rename = "any")]
+    Any { conditions: Vec<RuleCondition> },
+    #[serde(rename = "not")]
+    Not { condition: Box<RuleCondition> },
+}
+
+/// What a rule does with a matching file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    #[default]
+    Move,
+    /// Copies the file and leaves the original where it is.
+    Copy,
+    Symlink,
+    Hardlink,
+    /// Copies the file into the rule's destination and each of these (templates
+    /// like `destination`), leaving the original where it is.
+    MultiCopy { destinations: Vec<String> },
+}
+
+/// What a rule does when a file with identical content is already in the destination.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Place the file anyway, with a numbered name if its name is taken.
+    #[default]
+    KeepBoth,
+    /// Leave the incoming file where it is.
+    Skip,
+    /// Put the incoming file in place of the existing copy.
+    Replace,
+    /// Put the incoming file in this folder (a template like `destination`) instead.
+    DuplicatesFolder { folder: String },
+}
+
+/// What to do when a different file already has the target name in the destination.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Keep both, adding ` (N)` to the incoming file's name.
+    #[default]
+    KeepBoth,
+    /// Keep both, adding the incoming file's modification time to its name.
+    KeepBothTimestamp,
+    /// Replace the existing file if the incoming one was modified more recently,
+    /// otherwise leave the incoming file where it is.
+    OverwriteIfNewer,
+    /// Leave the incoming file where it is.
+    Skip,
+    /// Leave the incoming file where it is and queue it for the user to decide.
+    Ask,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +104,29 @@ pub struct Rule {
     pub name: Option<String>,
     pub condition: RuleCondition,
     pub destination: String,
+    // Optional file name template, e.g. "{date}-{slug}.{ext}", applied when the rule moves a file
+    #[serde(default)]
+    pub rename: Option<String>,
+    #[serde(default)]
+    pub action: RuleAction,
+    #[serde(default)]
+    pub on_duplicate: DuplicatePolicy,
+    /// Overrides `Config.conflict_policy` for this rule.
+    #[serde(default)]
+    pub on_conflict: Option<ConflictPolicy>,
+}
+
+/// A matched rule's templates expanded for one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedRule {
+    pub destination: String,
+    pub new_name: Option<String>,
+    /// The rule's action, with any extra destinations expanded too.
+    pub action: RuleAction,
+    /// With a duplicates folder expanded.
+    pub on_duplicate: DuplicatePolicy,
+    /// The rule's own policy, or the configured default.
+    pub on_conflict: ConflictPolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,11 +136,140 @@ pub struct PendingFile {
     pub extension: String,
     pub size: u64,
     pub detected_at: String,
+    /// Why the file needs a decision when it isn't just unmatched, e.g. a name conflict.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Detected from the file's content, or its extension when that fails.
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// The rule that matches the file, if any.
+    #[serde(default)]
+    pub matched_rule: Option<RuleMatch>,
+    /// Destinations to offer, best first.
+    #[serde(default)]
+    pub suggestions: Vec<Suggestion>,
+    /// Path of a cached preview image, for images only.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+}
+
+/// A folder to watch. Without its own mode or rules it uses the top-level ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchEntry {
+    pub path: String,
+    #[serde(default)]
+    pub organization_mode: Option<String>,
+    #[serde(default)]
+    pub rules: Option<Vec<Rule>>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub recursive: bool,
+    // Deepest subfolder level watched when recursive; files directly in `path` are level 0
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    // Subfolders to skip, as globs on the folder name or its path below `path`
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<String>,
+}
+
+/// The form watched folder paths are stored and compared in: resolved through
+/// symlinks and to the on-disk case where the folder exists (notify reports
+/// resolved paths, e.g. `/private/tmp` for `/tmp` on macOS), and otherwise
+/// without trailing separators, `.` or `..`.
+pub fn normalize_folder(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        Ok(resolved) => strip_verbatim_prefix(resolved),
+        Err(_) => {
+            let mut normal = PathBuf::new();
+            for component in path.components() {
+                match component {
+                    Component::CurDir => {}
+                    Component::ParentDir if normal.file_name().is_some() => {
+                        normal.pop();
+                    }
+                    _ => normal.push(component),
+                }
+            }
+            normal
+        }
+    }
+}
+
+/// `canonicalize` returns `\\?\C:\...` paths on Windows, which never compare
+/// equal to the plain paths the watcher reports.
+#[cfg(windows)]
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let plain = path
+        .to_str()
+        .and_then(|text| text.strip_prefix(r"\\?\"))
+        .filter(|rest| !rest.starts_with(r"UNC\"))
+        .map(PathBuf::from);
+    plain.unwrap_or(path)
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    path
+}
+
+impl WatchEntry {
+    pub fn new(path: impl Into<String>) -> Self {
+        WatchEntry {
+            path: normalize_folder(Path::new(&path.into())).to_string_lossy().to_string(),
+            organization_mode: None,
+            rules: None,
+            enabled: true,
+            recursive: false,
+            max_depth: None,
+            exclude: default_exclude(),
+        }
+    }
+
+    pub fn is_for(&self, folder: &Path) -> bool {
+        normalize_folder(Path::new(&self.path)) == normalize_folder(folder)
+    }
+
+    /// Whether files directly in `dir` belong to this entry, given its depth limit,
+    /// its exclude patterns and the folders in `skip`.
+    pub fn includes_dir(&self, dir: &Path, skip: &[PathBuf]) -> bool {
+        let Ok(relative) = dir.strip_prefix(&self.path) else {
+            return false;
+        };
+        let depth = relative.components().count();
+        if depth > 0 && !self.recursive {
+            return false;
+        }
+        if self.max_depth.is_some_and(|max| depth > max) {
+            return false;
+        }
+
+        let mut subpath = String::new();
+        for component in relative.components() {
+            let name = component.as_os_str().to_string_lossy();
+            if !subpath.is_empty() {
+                subpath.push('/');
+            }
+            subpath.push_str(&name);
+            if self
+                .exclude
+                .iter()
+                .any(|pattern| glob_matches(pattern, &name) || glob_matches(pattern, &subpath))
+            {
+                return false;
+            }
+        }
+        !skip.iter().any(|folder| dir.starts_with(folder))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    // Single folder from older configs; moved into `watched_folders` on load
+    #[serde(default, skip_serializing)]
     pub watched_folder: Option<String>,
+    #[serde(default)]
+    pub watched_folders: Vec<WatchEntry>,
     #[serde(default = "default_organization_mode")]
     pub organization_mode: String,
     #[serde(default)]
@@ -52,12 +285,70 @@ pub struct Config {
     pub auto_check_for_updates: bool,
     #[serde(default)]
     pub update_alert_suppress_until: Option<i64>, // Unix timestamp - suppress alerts until this time
+    // Templated destinations must resolve inside one of these folders (any folder when empty)
+    #[serde(default)]
+    pub allowed_destination_roots: Vec<String>,
+    // Organize files already in the watched folder when watching starts. Off by
+    // default, since it moves files the user never saw being picked up
+    #[serde(default)]
+    pub sweep_on_start: bool,
+    // A new file is handled once its size and modification time have been stable this long
+    #[serde(default = "default_settle_quiet_period_ms")]
+    pub settle_quiet_period_ms: u64,
+    // Files still changing after this long are left where they are
+    #[serde(default = "default_settle_max_wait_secs")]
+    pub settle_max_wait_secs: u64,
+    // File name globs (case-insensitive) for temporary and system files that are never organized
+    #[serde(default = "default_ignore_patterns")]
+    pub ignore_patterns: Vec<String>,
+    // What to do when a different file already has the target name, for rules without their own policy
+    #[serde(default)]
+    pub conflict_policy: ConflictPolicy,
 }
 
 fn default_show_menu_bar_icon() -> bool {
     true
 }
 
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_exclude() -> Vec<String> {
+    vec![".git".to_string(), "node_modules".to_string()]
+}
+
+fn default_settle_quiet_period_ms() -> u64 {
+    2000
+}
+
+fn default_settle_max_wait_secs() -> u64 {
+    600
+}
+
+fn default_ignore_patterns() -> Vec<String> {
+    [
+        // In-progress downloads
+        "*.download",
+        "*.crdownload",
+        "*.part",
+        "*.partial",
+        "*.opdownload",
+        "*.!qB",
+        "*.tmp",
+        // Office lock files
+        "~$*",
+        // Finder and Explorer metadata
+        ".DS_Store",
+        "._*",
+        "Thumbs.db",
+        "desktop.ini",
+    ]
+    .iter()
+    .map(|pattern| pattern.to_string())
+    .collect()
+}
+
 fn default_organization_mode() -> String {
     "both".to_string()
 }
@@ -66,6 +357,7 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             watched_folder: None,
+            watched_folders: Vec::new(),
             organization_mode: "both".to_string(),
             rules: Vec::new(),
             mappings: std::collections::HashMap::new(),
@@ -73,6 +365,12 @@ impl Default for Config {
             launch_at_login: false,
             auto_check_for_updates: true,
             update_alert_suppress_until: None,
+            allowed_destination_roots: Vec::new(),
+            sweep_on_start: false,
+            settle_quiet_period_ms: default_settle_quiet_period_ms(),
+            settle_max_wait_secs: default_settle_max_wait_secs(),
+            ignore_patterns: default_ignore_patterns(),
+            conflict_policy: ConflictPolicy::default(),
         }
     }
 }
@@ -82,7 +380,9 @@ impl Config {
         let config_path = Self::config_path();
         if config_path.exists() {
             if let Ok(content) = fs::read_to_string(&config_path) {
-                if let Ok(config) = serde_json::from_str::<Config>(&content) {
+                if let Ok(mut config) = serde_json::from_str::<Config>(&content) {
+                    config.migrate_watched_folder();
+                    config.normalize_watched_folders();
                     return config;
                 }
             }
@@ -90,6 +390,9 @@ impl Config {
         Self::default()
     }
 
+    /// Writes the config as it is. Callers that accept rules or patterns from the
+    /// user run `validate` first; watch-state changes don't, so a rule that no
+    /// longer validates can't stop a folder from being paused or removed.
     pub fn save(&self) -> Result<(), String> {
         let config_path = Self::config_path();
         if let Some(parent) = config_path.parent() {
@@ -100,29 +403,229 @@ impl Config {
         Ok(())
     }
 
+    /// Checks rule values that can only be interpreted at match time, so a
+    /// typo is reported when the config is saved instead of silently never matching.
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_rules(&self.rules)?;
+        for pattern in &self.ignore_patterns {
+            compile_glob(pattern).map_err(|e| format!("Invalid ignore pattern: {}", e))?;
+        }
+        for (index, entry) in self.watched_folders.iter().enumerate() {
+            if self.watched_folders[..index].iter().any(|other| other.path == entry.path) {
+                return Err(format!("Folder {} is listed more than once", entry.path));
+            }
+            if let Some(mode) = &entry.organization_mode {
+                if mode != "auto" && mode != "ask" && mode != "both" {
+                    return Err(format!("Invalid organization mode for {}: {}", entry.path, mode));
+                }
+            }
+            if let Some(rules) = &entry.rules {
+                self.validate_rules(rules)
+                    .map_err(|e| format!("{} (in {})", e, entry.path))?;
+            }
+            for pattern in &entry.exclude {
+                compile_glob(pattern).map_err(|e| format!("Invalid exclude pattern for {}: {}", entry.path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_rules(&self, rules: &[Rule]) -> Result<(), String> {
+        for (index, rule) in rules.iter().enumerate() {
+            rule.validate(&self.allowed_destination_roots).map_err(|e| {
+                let label = rule.name.clone().unwrap_or_else(|| format!("#{}", index + 1));
+                format!("Invalid rule {}: {}", label, e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Brings every watched folder path into `normalize_folder` form, dropping
+    /// entries that turn out to be the same folder as an earlier one.
+    pub fn normalize_watched_folders(&mut self) {
+        let mut seen = Vec::new();
+        self.watched_folders.retain_mut(|entry| {
+            let path = normalize_folder(Path::new(&entry.path));
+            if seen.contains(&path) {
+                return false;
+            }
+            entry.path = path.to_string_lossy().to_string();
+            seen.push(path);
+            true
+        });
+    }
+
+    /// Whether `path` is a temporary or system file that should be left alone.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_lowercase()) else {
+            return false;
+        };
+        self.ignore_patterns
+            .iter()
+            .any(|pattern| glob_matches(&pattern.to_lowercase(), &name))
+    }
+
+    /// Moves the single `watched_folder` of older configs into `watched_folders`.
+    pub fn migrate_watched_folder(&mut self) {
+        if let Some(folder) = self.watched_folder.take() {
+            let folder = folder.trim();
+            if !folder.is_empty() && !self.watched_folders.iter().any(|e| e.is_for(Path::new(folder))) {
+                self.watched_folders.push(WatchEntry::new(folder));
+            }
+        }
+    }
+
+    pub fn watch_entry(&self, folder: &Path) -> Option<&WatchEntry> {
+        self.watched_folders.iter().find(|entry| entry.is_for(folder))
+    }
+
+    pub fn watch_entry_mut(&mut self, folder: &Path) -> Option<&mut WatchEntry> {
+        self.watched_folders.iter_mut().find(|entry| entry.is_for(folder))
+    }
+
+    /// The entry whose folder most closely contains `file`, whether or not it is
+    /// enabled and however deep the file is.
+    pub fn watch_entry_containing(&self, file: &Path) -> Option<&WatchEntry> {
+        self.watched_folders
+            .iter()
+            .filter(|entry| file.starts_with(&entry.path) && !entry.is_for(file))
+            .max_by_key(|entry| Path::new(&entry.path).components().count())
+    }
+
+    /// The enabled entry that the watcher should handle `file` for, if any.
+    pub fn watch_entry_for_file(&self, file: &Path) -> Option<&WatchEntry> {
+        let entry = self.watch_entry_containing(file)?;
+        let dir = file.parent()?;
+        if entry.enabled && entry.includes_dir(dir, &self.destinations_inside(entry)) {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Fixed destination folders below `entry`'s folder. Files that were moved
+    /// there must not be picked up again by a recursive watch.
+    pub fn destinations_inside(&self, entry: &WatchEntry) -> Vec<PathBuf> {
+        let root = Path::new(&entry.path);
+        self.scoped(Some(entry))
+            .rules
+            .iter()
+            .flat_map(|rule| rule.destination_templates())
+            .filter(|template| !template.is_empty())
+            .filter_map(destination_root)
+            .filter(|folder| folder.starts_with(root) && folder != root)
+            .collect()
+    }
+
+    /// The path rules see for `file`: relative to its watched folder with `/`
+    /// separators, or just the file name outside of any watched folder.
+    pub fn subpath(&self, file: &Path) -> String {
+        let relative = self
+            .watch_entry_containing(file)
+            .and_then(|entry| file.strip_prefix(&entry.path).ok());
+        match relative {
+            Some(relative) => relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            None => file
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The settings that apply to files in `folder`: the folder's own mode and
+    /// rules where its watch entry has them, the top-level ones otherwise.
+    pub fn for_folder(&self, folder: &Path) -> Config {
+        self.scoped(self.watch_entry(folder))
+    }
+
+    /// Like `for_folder`, for a file anywhere below a watched folder.
+    pub fn for_file(&self, file: &Path) -> Config {
+        self.scoped(self.watch_entry_containing(file))
+    }
+
+    fn scoped(&self, entry: Option<&WatchEntry>) -> Config {
+        let mut scoped = self.clone();
+        if let Some(entry) = entry {
+            if let Some(mode) = &entry.organization_mode {
+                scoped.organization_mode = mode.clone();
+            }
+            if let Some(rules) = &entry.rules {
+                scoped.rules = rules.clone();
+            }
+        }
+        scoped
+    }
+
     pub fn config_path() -> PathBuf {
-        let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+        // Tests must never touch the user's settings, history or set-aside files
+        let mut path = if cfg!(test) {
+            std::env::temp_dir().join(format!("folder-watcher-test-config-{}", std::process::id()))
+        } else {
+            dirs::config_dir().unwrap_or_else(|| PathBuf::from("."))
+        };
         path.push("folder-watcher");
         path.push("config.json");
         path
     }
 
-    pub fn get_destination_folder(
+    /// The first rule with a destination that matches the file, with its index in `rules`.
+    pub fn find_matching_rule(
         &self,
         file_path: &PathBuf,
         file_extension: &str,
         file_name: &str,
         created_date: Option<std::time::SystemTime>,
-    ) -> Option<String> {
+    ) -> Option<(usize, &Rule)> {
+        let subpath = self.subpath(file_path);
         // Only use rules-based matching - no fallbacks
-        for rule in &self.rules {
-            if rule.matches(file_path, file_extension, file_name, created_date) {
-                if !rule.destination.is_empty() {
-                    return Some(rule.destination.clone());
-                }
-            }
-        }
-        None
+        self.rules.iter().enumerate().find(|(_, rule)| {
+            !rule.destination.is_empty()
+                && rule.matches(file_path, file_extension, file_name, &subpath, created_date)
+        })
+    }
+
+    /// Expands a matched rule's destination template, and its rename template if it has one.
+    pub fn resolve_rule(
+        &self,
+        rule: &Rule,
+        file_path: &PathBuf,
+        file_name: &str,
+    ) -> Result<ResolvedRule, String> {
+        let mut captures = HashMap::new();
+        rule.condition.collect_captures(file_name, &mut captures);
+        let context = TemplateContext::new(file_path, captures);
+        let destination = expand_destination(&rule.destination, &context, &self.allowed_destination_roots)?;
+        let new_name = rule
+            .rename
+            .as_deref()
+            .map(|template| expand_file_name(template, &context))
+            .transpose()?;
+        let action = match &rule.action {
+            RuleAction::MultiCopy { destinations } => RuleAction::MultiCopy {
+                destinations: destinations
+                    .iter()
+                    .map(|template| expand_destination(template, &context, &self.allowed_destination_roots))
+                    .collect::<Result<_, _>>()?,
+            },
+            action => action.clone(),
+        };
+        let on_duplicate = match &rule.on_duplicate {
+            DuplicatePolicy::DuplicatesFolder { folder } => DuplicatePolicy::DuplicatesFolder {
+                folder: expand_destination(folder, &context, &self.allowed_destination_roots)?,
+            },
+            policy => policy.clone(),
+        };
+        Ok(ResolvedRule {
+            destination,
+            new_name,
+            action,
+            on_duplicate,
+            on_conflict: rule.on_conflict.unwrap_or(self.conflict_policy),
+        })
     }
     
     pub fn should_show_update_alert(&self) -> bool {
@@ -153,9 +656,62 @@ impl Rule {
         file_path: &PathBuf,
         file_extension: &str,
         file_name: &str,
+        subpath: &str,
+        created_date: Option<std::time::SystemTime>,
+    ) -> bool {
+        self.condition.matches(file_path, file_extension, file_name, subpath, created_date)
+    }
+
+    /// Every folder template the rule can put files in.
+    pub fn destination_templates(&self) -> Vec<&str> {
+        let mut templates = vec![self.destination.as_str()];
+        if let RuleAction::MultiCopy { destinations } = &self.action {
+            templates.extend(destinations.iter().map(|d| d.as_str()));
+        }
+        if let DuplicatePolicy::DuplicatesFolder { folder } = &self.on_duplicate {
+            templates.push(folder);
+        }
+        templates
+    }
+
+    pub fn validate(&self, allowed_roots: &[String]) -> Result<(), String> {
+        self.condition.validate()?;
+        let capture_names = self.condition.capture_names();
+        if !self.destination.is_empty() {
+            validate_destination(&self.destination, &capture_names, allowed_roots)?;
+        }
+        if let Some(rename) = &self.rename {
+            validate_file_name(rename, &capture_names)?;
+        }
+        if let RuleAction::MultiCopy { destinations } = &self.action {
+            if destinations.is_empty() {
+                return Err("'multi_copy' needs at least one extra destination".to_string());
+            }
+            for destination in destinations {
+                validate_destination(destination, &capture_names, allowed_roots)?;
+            }
+        }
+        if let DuplicatePolicy::DuplicatesFolder { folder } = &self.on_duplicate {
+            if folder.is_empty() {
+                return Err("'duplicates_folder' needs a folder".to_string());
+            }
+            validate_destination(folder, &capture_names, allowed_roots)?;
+        }
+        Ok(())
+    }
+}
+
+impl RuleCondition {
+    /// `subpath` is the file's path below its watched folder, see `Config::subpath`.
+    pub fn matches(
+        &self,
+        file_path: &PathBuf,
+        file_extension: &str,
+        file_name: &str,
+        subpath: &str,
         created_date: Option<std::time::SystemTime>,
     ) -> bool {
-        match &self.condition {
+        match self {
             RuleCondition::FileType { value } => {
                 let ext = file_extension.to_lowercase();
                 value.to_lowercase() == ext || value.to_lowercase() == "*"
@@ -163,10 +719,7 @@ impl Rule {
             RuleCondition::Name { pattern } => {
                 // Simple pattern matching - supports * wildcard
                 if pattern.contains('*') {
-                    let regex_pattern = pattern
-                        .replace("*", ".*")
-                        .replace(".", "\\.");
-                    if let Ok(re) = Regex::new(&format!("^{}$", regex_pattern)) {
+                    if let Ok(re) = compile_glob(pattern) {
                         re.is_match(file_name)
                     } else {
                         file_name.contains(pattern)
@@ -175,23 +728,475 @@ impl Rule {
                     file_name.contains(pattern)
                 }
             }
+            RuleCondition::Glob { pattern } => compile_glob(pattern)
+                .map(|re| re.is_match(file_name))
+                .unwrap_or(false),
+            RuleCondition::Regex { pattern } => Regex::new(pattern)
+                .map(|re| re.is_match(file_name))
+                .unwrap_or(false),
+            RuleCondition::Path { pattern } => glob_matches(pattern, subpath),
             RuleCondition::CreatedDate { operator, value } => {
-                if let Some(created) = created_date {
-                    // Parse the value as a date and compare
-                    // For now, simple implementation - can be enhanced
-                    match operator.as_str() {
-                        "before" | "after" | "on" => {
-                            // TODO: Implement date parsing and comparison
-                            // For now, return false to avoid incorrect matches
-                            false
-                        }
+                // Not every filesystem records a creation time, so fall back to the modification time
+                let timestamp = created_date
+                    .or_else(|| file_path.metadata().ok().and_then(|m| m.modified().ok()));
+                let Some(timestamp) = timestamp else {
+                    return false;
+                };
+                let file_time: DateTime<Local> = timestamp.into();
+
+                match parse_date_range(value, Local::now()) {
+                    Ok((start, end)) => match operator.as_str() {
+                        "before" => file_time < start,
+                        "after" => file_time >= end,
+                        "on" => file_time >= start && file_time < end,
+                        _ => false,
+                    },
+                    Err(_) => false,
+                }
+            }
+            RuleCondition::Size { operator, value, max } => {
+                let Ok(metadata) = file_path.metadata() else {
+                    return false;
+                };
+                let size = metadata.len();
+                let Ok(bound) = parse_size(value) else {
+                    return false;
+                };
+                match operator.as_str() {
+                    "greater_than" => size > bound,
+                    "less_than" => size < bound,
+                    "between" => match max.as_deref().map(parse_size) {
+                        Some(Ok(upper)) => size >= bound && size <= upper,
                         _ => false,
+                    },
+                    _ => false,
+                }
+            }
+            RuleCondition::MimeType { value } => file_type::detect(file_path)
+                .map(|detected| file_type::mime_matches(value, detected.mime))
+                .unwrap_or(false),
+            RuleCondition::Kind { value } => {
+                let kind = file_type::detect(file_path)
+                    .map(|detected| detected.kind)
+                    .unwrap_or(FileKind::Other);
+                kind.as_str() == value.trim().to_lowercase()
+            }
+            // An empty `all` matches everything and an empty `any` matches nothing,
+            // the same as `Iterator::all` / `Iterator::any`
+            RuleCondition::All { conditions } => conditions
+                .iter()
+                .all(|c| c.matches(file_path, file_extension, file_name, subpath, created_date)),
+            RuleCondition::Any { conditions } => conditions
+                .iter()
+                .any(|c| c.matches(file_path, file_extension, file_name, subpath, created_date)),
+            RuleCondition::Not { condition } => {
+                !condition.matches(file_path, file_extension, file_name, subpath, created_date)
+            }
+        }
+    }
+
+    /// Collects named capture groups from every `Regex` condition that matches
+    /// `file_name`. Negated conditions never contribute captures.
+    pub fn collect_captures(&self, file_name: &str, captures: &mut HashMap<String, String>) {
+        match self {
+            RuleCondition::Regex { pattern } => {
+                let Ok(re) = Regex::new(pattern) else {
+                    return;
+                };
+                if let Some(caps) = re.captures(file_name) {
+                    for name in re.capture_names().flatten() {
+                        if let Some(value) = caps.name(name) {
+                            captures
+                                .entry(name.to_string())
+                                .or_insert_with(|| value.as_str().to_string());
+                        }
                     }
-                } else {
-                    false
                 }
             }
+            RuleCondition::All { conditions } | RuleCondition::Any { conditions } => {
+                for condition in conditions {
+                    condition.collect_captures(file_name, captures);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Names of the capture groups this condition can provide to a destination.
+    pub fn capture_names(&self) -> Vec<String> {
+        match self {
+            RuleCondition::Regex { pattern } => Regex::new(pattern)
+                .map(|re| re.capture_names().flatten().map(|n| n.to_string()).collect())
+                .unwrap_or_default(),
+            RuleCondition::All { conditions } | RuleCondition::Any { conditions } => {
+                conditions.iter().flat_map(|c| c.capture_names()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RuleCondition::Glob { pattern } | RuleCondition::Path { pattern } => {
+                compile_glob(pattern).map(|_| ())
+            }
+            RuleCondition::Regex { pattern } => Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|e| format!("invalid regex '{}': {}", pattern, e)),
+            RuleCondition::CreatedDate { operator, value } => {
+                if !matches!(operator.as_str(), "before" | "after" | "on") {
+                    return Err(format!(
+                        "unknown date operator '{}' (expected 'before', 'after' or 'on')",
+                        operator
+                    ));
+                }
+                parse_date_range(value, Local::now()).map(|_| ())
+            }
+            RuleCondition::Size { operator, value, max } => {
+                let lower = parse_size(value)?;
+                match operator.as_str() {
+                    "greater_than" | "less_than" => Ok(()),
+                    "between" => {
+                        let max = max.as_deref().ok_or("'between' needs a maximum size")?;
+                        if parse_size(max)? < lower {
+                            return Err(format!("size range {} to {} is empty", value, max));
+                        }
+                        Ok(())
+                    }
+                    _ => Err(format!(
+                        "unknown size operator '{}' (expected 'greater_than', 'less_than' or 'between')",
+                        operator
+                    )),
+                }
+            }
+            RuleCondition::Kind { value } => {
+                serde_json::from_value::<FileKind>(serde_json::Value::String(value.trim().to_lowercase()))
+                    .map(|_| ())
+                    .map_err(|_| format!(
+                        "unknown kind '{}' (expected image, archive, document, audio, video or other)",
+                        value
+                    ))
+            }
+            RuleCondition::All { conditions } | RuleCondition::Any { conditions } => {
+                conditions.iter().try_for_each(|c| c.validate())
+            }
+            RuleCondition::Not { condition } => condition.validate(),
+            _ => Ok(()),
         }
     }
 }
 
+/// Parses a human-readable size such as `500MB`, `2 GiB`, `1.5 gb` or `1024` into bytes.
+///
+/// SI units (`KB`, `MB`, `GB`, `TB`) are powers of 1000 and IEC units (`KiB`, `MiB`,
+/// `GiB`, `TiB`) powers of 1024. A bare number is a byte count.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, value)),
+    };
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes > u64::MAX as f64 {
+        return Err(format!("size '{}' is out of range", value));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Resolves a date condition value to the half-open interval `[start, end)` it covers.
+///
+/// Accepts ISO-8601 dates (`2024-03-01`), date-times (`2024-03-01T12:00:00`, with or
+/// without an offset) and relative expressions: `today`, `yesterday`, `N days ago`,
+/// `N weeks ago`, `N months ago`, `N years ago`, and `this`/`last` followed by
+/// `week`, `month` or `year`. Whole days, weeks, months and years cover their full
+/// calendar span in local time, so "before last month" means before its first day.
+pub fn parse_date_range(
+    value: &str,
+    now: DateTime<Local>,
+) -> Result<(DateTime<Local>, DateTime<Local>), String> {
+    let value = value.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return day_range(date, date + Duration::days(1));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        let start = datetime.with_timezone(&Local);
+        return Ok((start, start + Duration::seconds(1)));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            let start = local_from_naive(naive)?;
+            return Ok((start, start + Duration::seconds(1)));
+        }
+    }
+
+    let today = now.date_naive();
+    let lowered = value.to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+    let (start, end) = match words.as_slice() {
+        ["today"] => (today, today + Duration::days(1)),
+        ["yesterday"] => (today - Duration::days(1), today),
+        [count, unit, "ago"] => {
+            let count: u32 = count
+                .parse()
+                .map_err(|_| format!("invalid number '{}' in date '{}'", count, value))?;
+            let day = match unit.trim_end_matches('s') {
+                "day" => today.checked_sub_signed(Duration::days(count as i64)),
+                "week" => today.checked_sub_signed(Duration::weeks(count as i64)),
+                "month" => today.checked_sub_months(Months::new(count)),
+                "year" => today.checked_sub_months(Months::new(count.saturating_mul(12))),
+                _ => return Err(format!("unknown time unit '{}' in date '{}'", unit, value)),
+            }
+            .ok_or_else(|| format!("date '{}' is out of range", value))?;
+            (day, day + Duration::days(1))
+        }
+        [which @ ("this" | "last"), unit] => {
+            let (start, end) = match *unit {
+                "week" => {
+                    let start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                    (start, start + Duration::weeks(1))
+                }
+                "month" => {
+                    let start = today.with_day(1).expect("day 1 exists in every month");
+                    (start, start + Months::new(1))
+                }
+                "year" => {
+                    let start = NaiveDate::from_ymd_opt(today.year(), 1, 1)
+                        .ok_or_else(|| format!("date '{}' is out of range", value))?;
+                    (start, start + Months::new(12))
+                }
+                _ => return Err(format!("unknown time unit '{}' in date '{}'", unit, value)),
+            };
+            if *which == "this" {
+                (start, end)
+            } else {
+                // The previous period ends where the current one starts
+                let length_months = match *unit {
+                    "month" => Some(1),
+                    "year" => Some(12),
+                    _ => None,
+                };
+                match length_months {
+                    Some(months) => (start - Months::new(months), start),
+                    None => (start - Duration::weeks(1), start),
+                }
+            }
+        }
+        _ => {
+            return Err(format!(
+                "unrecognized date '{}' (use YYYY-MM-DD or an expression like '7 days ago' or 'last month')",
+                value
+            ))
+        }
+    };
+    day_range(start, end)
+}
+
+fn day_range(start: NaiveDate, end: NaiveDate) -> Result<(DateTime<Local>, DateTime<Local>), String> {
+    Ok((
+        local_from_naive(start.and_hms_opt(0, 0, 0).unwrap_or_default())?,
+        local_from_naive(end.and_hms_opt(0, 0, 0).unwrap_or_default())?,
+    ))
+}
+
+fn local_from_naive(naive: NaiveDateTime) -> Result<DateTime<Local>, String> {
+    // `earliest` resolves times that are ambiguous around a DST change
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| format!("{} does not exist in the local time zone", naive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use std::time::SystemTime;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, min, s).earliest().unwrap()
+    }
+
+    fn midnight(y: i32, m: u32, d: u32) -> DateTime<Local> {
+        local(y, m, d, 0, 0, 0)
+    }
+
+    #[test]
+    fn parses_absolute_dates() {
+        let now = local(2024, 3, 13, 15, 0, 0);
+        let cases = [
+            ("2024-03-01", midnight(2024, 3, 1), midnight(2024, 3, 2)),
+            (" 2024-02-29 ", midnight(2024, 2, 29), midnight(2024, 3, 1)),
+            ("2024-03-01T12:30:00", local(2024, 3, 1, 12, 30, 0), local(2024, 3, 1, 12, 30, 1)),
+            ("2024-03-01T12:30", local(2024, 3, 1, 12, 30, 0), local(2024, 3, 1, 12, 30, 1)),
+            ("2024-03-01 12:30:15", local(2024, 3, 1, 12, 30, 15), local(2024, 3, 1, 12, 30, 16)),
+        ];
+        for (value, start, end) in cases {
+            assert_eq!(parse_date_range(value, now), Ok((start, end)), "{}", value);
+        }
+
+        let (start, end) = parse_date_range("2024-03-01T12:30:00+00:00", now).unwrap();
+        assert_eq!(start, DateTime::parse_from_rfc3339("2024-03-01T12:30:00Z").unwrap());
+        assert_eq!(end - start, Duration::seconds(1));
+    }
+
+    #[test]
+    fn parses_relative_dates() {
+        // A Wednesday
+        let now = local(2024, 3, 13, 15, 0, 0);
+        let cases = [
+            ("today", midnight(2024, 3, 13), midnight(2024, 3, 14)),
+            ("yesterday", midnight(2024, 3, 12), midnight(2024, 3, 13)),
+            ("1 day ago", midnight(2024, 3, 12), midnight(2024, 3, 13)),
+            ("3 days ago", midnight(2024, 3, 10), midnight(2024, 3, 11)),
+            ("2 weeks ago", midnight(2024, 2, 28), midnight(2024, 2, 29)),
+            ("1 month ago", midnight(2024, 2, 13), midnight(2024, 2, 14)),
+            ("1 year ago", midnight(2023, 3, 13), midnight(2023, 3, 14)),
+            ("this week", midnight(2024, 3, 11), midnight(2024, 3, 18)),
+            ("last week", midnight(2024, 3, 4), midnight(2024, 3, 11)),
+            ("this month", midnight(2024, 3, 1), midnight(2024, 4, 1)),
+            ("last month", midnight(2024, 2, 1), midnight(2024, 3, 1)),
+            ("this year", midnight(2024, 1, 1), midnight(2025, 1, 1)),
+            ("last year", midnight(2023, 1, 1), midnight(2024, 1, 1)),
+            ("  Last   Month ", midnight(2024, 2, 1), midnight(2024, 3, 1)),
+        ];
+        for (value, start, end) in cases {
+            assert_eq!(parse_date_range(value, now), Ok((start, end)), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_unrecognized_dates() {
+        let now = local(2024, 3, 13, 15, 0, 0);
+        for value in ["", "soon", "next week", "few days ago", "3 fortnights ago", "last decade", "2024-13-01", "01/03/2024"] {
+            assert!(parse_date_range(value, now).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn created_date_operators_use_half_open_days() {
+        let cases = [
+            ("before", local(2024, 2, 29, 23, 59, 59), true),
+            ("before", midnight(2024, 3, 1), false),
+            ("on", local(2024, 2, 29, 23, 59, 59), false),
+            ("on", midnight(2024, 3, 1), true),
+            ("on", local(2024, 3, 1, 23, 59, 59), true),
+            ("on", midnight(2024, 3, 2), false),
+            ("after", local(2024, 3, 1, 23, 59, 59), false),
+            ("after", midnight(2024, 3, 2), true),
+            ("since", midnight(2024, 3, 2), false),
+        ];
+        let file = PathBuf::from("/nonexistent/report.pdf");
+        for (operator, created, expected) in cases {
+            let condition = RuleCondition::CreatedDate {
+                operator: operator.to_string(),
+                value: "2024-03-01".to_string(),
+            };
+            let created = Some(SystemTime::from(created));
+            assert_eq!(
+                condition.matches(&file, "pdf", "report.pdf", "report.pdf", created),
+                expected,
+                "{} {:?}",
+                operator,
+                created
+            );
+        }
+    }
+
+    #[test]
+    fn parses_sizes_with_units() {
+        let cases = [
+            ("0", 0),
+            ("1024", 1024),
+            ("500B", 500),
+            ("500 b", 500),
+            ("1k", 1_000),
+            ("1KB", 1_000),
+            ("1 kb", 1_000),
+            ("1.5MB", 1_500_000),
+            ("1.5 gb", 1_500_000_000),
+            ("2TB", 2_000_000_000_000),
+            ("0.5KiB", 512),
+            ("  10 MiB ", 10 << 20),
+            ("2 GiB", 2 << 30),
+            ("1 tib", 1 << 40),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(parse_size(value), Ok(bytes), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_sizes() {
+        for value in ["", "MB", "abc", "1.2.3MB", "5 XB", "-1MB", "1e3", "1 000", "99999999999 TiB"] {
+            assert!(parse_size(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn size_operators() {
+        let dir = scratch_dir("config-size");
+        let file = dir.join("data.bin");
+        fs::write(&file, vec![0u8; 1500]).unwrap();
+
+        let cases = [
+            ("greater_than", "1KB", None, true),
+            ("greater_than", "1.5KB", None, false),
+            ("less_than", "1.5KB", None, false),
+            ("less_than", "2KB", None, true),
+            // Both ends of a range are included
+            ("between", "1KB", Some("1.5KB"), true),
+            ("between", "1.5KB", Some("2KB"), true),
+            ("between", "1KB", Some("1.4KB"), false),
+            ("between", "1KB", None, false),
+            ("at_least", "1KB", None, false),
+            ("greater_than", "lots", None, false),
+        ];
+        for (operator, value, max, expected) in cases {
+            let condition = RuleCondition::Size {
+                operator: operator.to_string(),
+                value: value.to_string(),
+                max: max.map(|m| m.to_string()),
+            };
+            assert_eq!(
+                condition.matches(&file, "bin", "data.bin", "data.bin", None),
+                expected,
+                "{} {} {:?}",
+                operator,
+                value,
+                max
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn validates_size_ranges() {
+        let size = |operator: &str, value: &str, max: Option<&str>| RuleCondition::Size {
+            operator: operator.to_string(),
+            value: value.to_string(),
+            max: max.map(|m| m.to_string()),
+        };
+        assert!(size("between", "1MB", Some("2MB")).validate().is_ok());
+        assert!(size("between", "1MB", None).validate().is_err());
+        assert!(size("between", "2MB", Some("1MB")).validate().is_err());
+        assert!(size("greater_than", "1 XB", None).validate().is_err());
+        assert!(size("at_least", "1MB", None).validate().is_err());
+    }
+}
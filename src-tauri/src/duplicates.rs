@@ -0,0 +1,109 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// SHA-256 of a file's content, hex encoded.
+pub fn content_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Looks in `folder` (not its subfolders) for a file with the same content as
+/// `file`, trying `preferred_name` first. Only files of the same size are hashed.
+pub fn find_duplicate(file: &Path, folder: &Path, preferred_name: &str) -> Option<PathBuf> {
+    let size = file.metadata().ok()?.len();
+    let same_size = |path: &Path| {
+        path != file
+            && path
+                .symlink_metadata()
+                .is_ok_and(|m| m.is_file() && m.len() == size)
+    };
+
+    let preferred = folder.join(preferred_name);
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    if same_size(&preferred) {
+        candidates.push(preferred.clone());
+    }
+    if let Ok(entries) = fs::read_dir(folder) {
+        candidates.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| *path != preferred && same_size(path)),
+        );
+    }
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let hash = content_hash(file).ok()?;
+    candidates
+        .into_iter()
+        .find(|candidate| content_hash(candidate).is_ok_and(|h| h == hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+
+    #[test]
+    fn same_size_with_different_content_is_not_a_duplicate() {
+        let dir = scratch_dir("duplicates-size");
+        let file = dir.join("incoming.txt");
+        fs::write(&file, "abcd").unwrap();
+        let folder = dir.join("destination");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("incoming.txt"), "abce").unwrap();
+        fs::write(folder.join("other.txt"), "wxyz").unwrap();
+
+        assert_eq!(find_duplicate(&file, &folder, "incoming.txt"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn preferred_name_is_found_first() {
+        let dir = scratch_dir("duplicates-preferred");
+        let file = dir.join("incoming.txt");
+        fs::write(&file, "same").unwrap();
+        let folder = dir.join("destination");
+        fs::create_dir_all(&folder).unwrap();
+        for name in ["a.txt", "incoming.txt", "z.txt"] {
+            fs::write(folder.join(name), "same").unwrap();
+        }
+
+        assert_eq!(find_duplicate(&file, &folder, "incoming.txt"), Some(folder.join("incoming.txt")));
+        assert!(find_duplicate(&file, &folder, "missing.txt").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn subfolders_are_ignored() {
+        let dir = scratch_dir("duplicates-subfolder");
+        let file = dir.join("incoming.txt");
+        fs::write(&file, "same").unwrap();
+        let folder = dir.join("destination");
+        fs::create_dir_all(folder.join("nested")).unwrap();
+        fs::write(folder.join("nested").join("incoming.txt"), "same").unwrap();
+
+        assert_eq!(find_duplicate(&file, &folder, "incoming.txt"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
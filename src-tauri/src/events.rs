@@ -0,0 +1,233 @@
+use crate::file_organizer::{Conflict, Organized};
+use crate::history::HistoryAction;
+use crate::routing::RuleMatch;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Tauri event name that watcher events are forwarded to the frontend under.
+pub const WATCHER_EVENT: &str = "watcher-event";
+
+/// Everything the watcher reports about the files it handles.
+///
+/// Serialized with a `type` tag, e.g. `{"type": "moved", "source": ..., "timestamp": ...}`.
+/// Timestamps are Unix seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WatcherEvent {
+    /// A file was organized into a destination; `action` says whether it was
+    /// moved, copied or linked there.
+    Moved {
+        source: PathBuf,
+        destination: PathBuf,
+        rule: Option<RuleMatch>,
+        action: HistoryAction,
+        /// A file with identical content that was already in the destination.
+        duplicate_of: Option<PathBuf>,
+        /// How a clash with a different file of the same name was resolved.
+        conflict: Option<Conflict>,
+        timestamp: u64,
+    },
+    Queued {
+        path: PathBuf,
+        name: String,
+        size: u64,
+        /// Why the file needs a decision, when it isn't just unmatched.
+        reason: Option<String>,
+        timestamp: u64,
+    },
+    /// A file was left in place, because the destination already has a copy
+    /// (`duplicate_of`) or a different file with the same name (`conflict`).
+    Skipped {
+        path: PathBuf,
+        duplicate_of: Option<PathBuf>,
+        conflict: Option<Conflict>,
+        timestamp: u64,
+    },
+    /// A file could not be organized or queued.
+    Error {
+        path: Option<PathBuf>,
+        message: String,
+        timestamp: u64,
+    },
+    /// The underlying filesystem watcher failed.
+    WatchError {
+        message: String,
+        timestamp: u64,
+    },
+    /// No rule matched a file in auto mode, so it was left in place.
+    RuleMiss {
+        path: PathBuf,
+        timestamp: u64,
+    },
+    /// A new file is waiting for its size and modification time to stop changing.
+    Settling {
+        path: PathBuf,
+        timestamp: u64,
+    },
+    /// A settling file is complete and is about to be organized.
+    Settled {
+        path: PathBuf,
+        timestamp: u64,
+    },
+    /// A settling file was removed or renamed before it settled.
+    Vanished {
+        path: PathBuf,
+        timestamp: u64,
+    },
+    /// A sweep of the files already in a watched folder began.
+    SweepStarted {
+        folder: PathBuf,
+        total: usize,
+        timestamp: u64,
+    },
+    SweepProgress {
+        path: PathBuf,
+        processed: usize,
+        total: usize,
+        timestamp: u64,
+    },
+    SweepFinished {
+        folder: PathBuf,
+        processed: usize,
+        total: usize,
+        cancelled: bool,
+        timestamp: u64,
+    },
+    /// A large file is being copied to another volume as part of a move.
+    TransferProgress {
+        path: PathBuf,
+        copied: u64,
+        total: u64,
+        timestamp: u64,
+    },
+}
+
+impl WatcherEvent {
+    /// `Moved` for a file that was placed, `Skipped` for one left where it was.
+    pub fn organized(source: impl Into<PathBuf>, organized: &Organized, rule: Option<RuleMatch>) -> Self {
+        let duplicate_of = organized.duplicate_of.as_ref().map(PathBuf::from);
+        if organized.action == HistoryAction::Skipped {
+            return WatcherEvent::Skipped {
+                path: source.into(),
+                duplicate_of,
+                conflict: organized.conflict.clone(),
+                timestamp: now(),
+            };
+        }
+        WatcherEvent::Moved {
+            source: source.into(),
+            destination: PathBuf::from(&organized.path),
+            rule,
+            action: organized.action,
+            duplicate_of,
+            conflict: organized.conflict.clone(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn queued(path: impl Into<PathBuf>, name: String, size: u64, reason: Option<String>) -> Self {
+        WatcherEvent::Queued {
+            path: path.into(),
+            name,
+            size,
+            reason,
+            timestamp: now(),
+        }
+    }
+
+    pub fn skipped(path: impl Into<PathBuf>) -> Self {
+        WatcherEvent::Skipped {
+            path: path.into(),
+            duplicate_of: None,
+            conflict: None,
+            timestamp: now(),
+        }
+    }
+
+    pub fn error(path: Option<PathBuf>, message: impl Into<String>) -> Self {
+        WatcherEvent::Error {
+            path,
+            message: message.into(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn watch_error(message: impl Into<String>) -> Self {
+        WatcherEvent::WatchError {
+            message: message.into(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn settling(path: impl Into<PathBuf>) -> Self {
+        WatcherEvent::Settling {
+            path: path.into(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn settled(path: impl Into<PathBuf>) -> Self {
+        WatcherEvent::Settled {
+            path: path.into(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn vanished(path: impl Into<PathBuf>) -> Self {
+        WatcherEvent::Vanished {
+            path: path.into(),
+            timestamp: now(),
+        }
+    }
+
+    pub fn sweep_started(folder: impl Into<PathBuf>, total: usize) -> Self {
+        WatcherEvent::SweepStarted {
+            folder: folder.into(),
+            total,
+            timestamp: now(),
+        }
+    }
+
+    pub fn sweep_progress(path: impl Into<PathBuf>, processed: usize, total: usize) -> Self {
+        WatcherEvent::SweepProgress {
+            path: path.into(),
+            processed,
+            total,
+            timestamp: now(),
+        }
+    }
+
+    pub fn sweep_finished(folder: impl Into<PathBuf>, processed: usize, total: usize, cancelled: bool) -> Self {
+        WatcherEvent::SweepFinished {
+            folder: folder.into(),
+            processed,
+            total,
+            cancelled,
+            timestamp: now(),
+        }
+    }
+
+    pub fn transfer_progress(path: impl Into<PathBuf>, copied: u64, total: u64) -> Self {
+        WatcherEvent::TransferProgress {
+            path: path.into(),
+            copied,
+            total,
+            timestamp: now(),
+        }
+    }
+
+    pub fn rule_miss(path: impl Into<PathBuf>) -> Self {
+        WatcherEvent::RuleMiss {
+            path: path.into(),
+            timestamp: now(),
+        }
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -1,75 +1,671 @@
-use crate::config::Config;
+use crate::config::{Config, ConflictPolicy, DuplicatePolicy, RuleAction};
+use crate::duplicates::find_duplicate;
+use crate::history::{self, HistoryAction, HistoryEntry, HistoryOutcome};
+use crate::routing::{classify_and_route, RoutingDecision, RuleMatch};
+use crate::templates::COUNTER_PLACEHOLDER;
+use crate::transfer::{self, Progress};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::SystemTime;
 
-pub fn organize_file(file_path: &Path, config: &Config) -> Result<String, String> {
-    let extension_str = file_path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_else(|| "other".to_string());
+/// How to place a file once its destination is known.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OrganizeOptions {
+    /// File name produced by a rename template.
+    pub new_name: Option<String>,
+    pub action: RuleAction,
+    pub on_duplicate: DuplicatePolicy,
+    pub on_conflict: ConflictPolicy,
+    pub rule: Option<RuleMatch>,
+}
 
-    let file_name = file_path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
+impl OrganizeOptions {
+    pub fn from_decision(decision: &RoutingDecision) -> Self {
+        OrganizeOptions {
+            new_name: decision.new_name.clone(),
+            action: decision.action.clone(),
+            on_duplicate: decision.on_duplicate.clone(),
+            on_conflict: decision.on_conflict,
+            rule: decision.matched_rule.clone(),
+        }
+    }
+}
 
-    let created_date = file_path
-        .metadata()
-        .ok()
-        .and_then(|m| m.created().ok());
+/// A different file already had the target name.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Conflict {
+    pub existing: String,
+    pub resolution: ConflictResolution,
+    /// Where an overwritten file was moved, so undo can put it back.
+    #[serde(default)]
+    pub backup: Option<String>,
+}
 
-    let path_buf = file_path.to_path_buf();
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// The incoming file was placed under another name.
+    KeptBoth,
+    /// The incoming file was newer and replaced the existing one.
+    Overwritten,
+    /// The incoming file was left where it was.
+    Skipped,
+    /// The incoming file was left where it was for the user to decide.
+    Deferred,
+}
 
-    let destination = config
-        .get_destination_folder(&path_buf, &extension_str, file_name, created_date)
-        .ok_or_else(|| format!("No matching rule found for file: {}", file_name))?;
+/// What happened to an organized file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Organized {
+    /// Where the file was placed, or its own path if it was left in place.
+    pub path: String,
+    pub action: HistoryAction,
+    /// A file with identical content that was already in the destination.
+    pub duplicate_of: Option<String>,
+    pub conflict: Option<Conflict>,
+}
 
-    let dest_path = PathBuf::from(&destination);
-    if !dest_path.exists() {
-        fs::create_dir_all(&dest_path)
-            .map_err(|e| format!("Failed to create destination folder: {}", e))?;
+impl Organized {
+    /// Whether the file was left in place for the user to decide about.
+    pub fn is_deferred(&self) -> bool {
+        self.conflict
+            .as_ref()
+            .is_some_and(|c| c.resolution == ConflictResolution::Deferred)
     }
+}
 
-    let file_name_obj = file_path
-        .file_name()
-        .ok_or_else(|| "Invalid file name".to_string())?;
+pub fn organize_file(file_path: &Path, config: &Config) -> Result<Organized, String> {
+    let decision = classify_and_route(file_path, config);
+    let options = OrganizeOptions::from_decision(&decision);
+    let destination = decision.destination.ok_or(decision.reason)?;
+    organize_file_to_destination(file_path, &destination, &options, None, None)
+}
 
-    let mut dest_file = dest_path.join(file_name_obj);
-    let mut counter = 1;
+/// The history action recorded for each placement made by `action`.
+pub fn history_action(action: &RuleAction) -> HistoryAction {
+    match action {
+        RuleAction::Move => HistoryAction::Moved,
+        RuleAction::Copy | RuleAction::MultiCopy { .. } => HistoryAction::Copied,
+        RuleAction::Symlink => HistoryAction::Symlinked,
+        RuleAction::Hardlink => HistoryAction::Hardlinked,
+    }
+}
 
-    while dest_file.exists() {
-        let stem = file_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("file");
-        let ext = file_path
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("");
-        let new_name = if ext.is_empty() {
-            format!("{} ({})", stem, counter)
-        } else {
-            format!("{} ({}).{}", stem, counter, ext)
+/// Moves, copies or links a file into `destination` (and, for `MultiCopy`, into
+/// each extra destination) and records every placement in the history log.
+///
+/// Returns what happened in `destination`. For `MultiCopy` every destination is
+/// attempted even if one fails, and any failure is returned. `progress` hears
+/// about moves that have to copy the file to another volume. `skips` lets files
+/// that were skipped before and haven't changed be passed over without hashing
+/// or recording them again.
+pub fn organize_file_to_destination(
+    file_path: &Path,
+    destination: &str,
+    options: &OrganizeOptions,
+    progress: Option<Progress>,
+    skips: Option<&SkipMemory>,
+) -> Result<Organized, String> {
+    let mut destinations = vec![destination];
+    if let RuleAction::MultiCopy { destinations: extra } = &options.action {
+        destinations.extend(extra.iter().map(|d| d.as_str()));
+    }
+
+    let mut placed = None;
+    let mut failures = Vec::new();
+    for destination in destinations {
+        match place_and_record(file_path, destination, options, progress, skips) {
+            Ok(organized) => {
+                placed.get_or_insert(organized);
+            }
+            Err(e) => failures.push(e),
+        }
+    }
+    match placed {
+        Some(organized) if failures.is_empty() => Ok(organized),
+        _ => Err(failures.join("; ")),
+    }
+}
+
+/// Where a file goes in one destination; `path` is `None` when it stays put.
+struct Placement {
+    path: Option<PathBuf>,
+    conflict: Option<Conflict>,
+}
+
+fn place_and_record(
+    file_path: &Path,
+    destination: &str,
+    options: &OrganizeOptions,
+    progress: Option<Progress>,
+    skips: Option<&SkipMemory>,
+) -> Result<Organized, String> {
+    let action = &options.action;
+    let file_name = target_file_name(file_path, options.new_name.as_deref())?;
+
+    if *action != RuleAction::Move {
+        // Left by an earlier run; nothing new to create or record
+        let existing = Path::new(destination).join(&file_name);
+        if already_placed(file_path, &existing, action) {
+            return Ok(Organized {
+                path: existing.to_string_lossy().to_string(),
+                action: history_action(action),
+                duplicate_of: None,
+                conflict: None,
+            });
+        }
+    }
+
+    if let Some(organized) = skips.and_then(|skips| skips.recall(file_path, destination, options)) {
+        return Ok(organized);
+    }
+
+    // Links don't duplicate content, so only moves and copies are checked
+    let duplicate = match action {
+        RuleAction::Symlink | RuleAction::Hardlink => None,
+        _ => find_duplicate(file_path, Path::new(destination), &file_name),
+    };
+
+    let size = file_path.metadata().ok().map(|m| m.len());
+    let result = match (&duplicate, &options.on_duplicate) {
+        (Some(_), DuplicatePolicy::Skip) => Ok(Placement {
+            path: None,
+            conflict: None,
+        }),
+        (Some(existing), DuplicatePolicy::Replace) => {
+            place_at(file_path, existing, action, progress).map(|()| Placement {
+                path: Some(existing.clone()),
+                conflict: None,
+            })
+        }
+        (Some(_), DuplicatePolicy::DuplicatesFolder { folder }) => {
+            place(file_path, folder, &file_name, action, ConflictPolicy::KeepBoth, progress)
+        }
+        _ => place(file_path, destination, &file_name, action, options.on_conflict, progress),
+    };
+
+    let replaced_duplicate = duplicate.is_some() && options.on_duplicate == DuplicatePolicy::Replace;
+    let duplicate_of = duplicate.map(|d| d.to_string_lossy().to_string());
+    let placement = match result {
+        Ok(placement) => placement,
+        Err(e) => {
+            let mut entry = HistoryEntry::new(history_action(action), file_path.to_string_lossy());
+            entry.rule = options.rule.clone();
+            entry.size = size;
+            entry.duplicate_of = duplicate_of;
+            entry.destination = Some(destination.to_string());
+            entry.outcome = HistoryOutcome::Failed { message: e.clone() };
+            history::record(entry);
+            return Err(e);
+        }
+    };
+
+    let organized = Organized {
+        path: placement
+            .path
+            .as_deref()
+            .unwrap_or(file_path)
+            .to_string_lossy()
+            .to_string(),
+        action: match placement.path {
+            Some(_) => history_action(action),
+            None => HistoryAction::Skipped,
+        },
+        duplicate_of,
+        conflict: placement.conflict,
+    };
+    // Nothing has happened yet to a file waiting for the user
+    if !organized.is_deferred() {
+        let mut entry = HistoryEntry::new(organized.action, file_path.to_string_lossy());
+        entry.rule = options.rule.clone();
+        entry.size = size;
+        entry.destination = placement.path.map(|_| organized.path.clone());
+        entry.duplicate_of = organized.duplicate_of.clone();
+        entry.replaced_duplicate = replaced_duplicate;
+        entry.conflict = organized.conflict.clone();
+        history::record(entry);
+    }
+    if let (Some(skips), HistoryAction::Skipped) = (skips, organized.action) {
+        if !organized.is_deferred() {
+            skips.remember(file_path, destination, options, &organized);
+        }
+    }
+    Ok(organized)
+}
+
+/// A file a skip policy left in place.
+struct SkippedFile {
+    /// The options it was skipped under; other options may not skip it.
+    options: OrganizeOptions,
+    /// Size and modification time of the source and of the file that caused the skip.
+    stamps: (FileStamp, FileStamp),
+    organized: Organized,
+}
+
+type FileStamp = (u64, SystemTime);
+
+/// Files a skip policy left in place, by source and destination. Skipped files
+/// stay in the watched folder, so every sweep sees them again; remembering them
+/// avoids hashing and recording them over and over.
+#[derive(Default)]
+pub struct SkipMemory {
+    skipped: Mutex<HashMap<(PathBuf, String), SkippedFile>>,
+}
+
+impl SkipMemory {
+    /// The earlier result for a file skipped in `destination` under the same
+    /// options, if neither the file nor the one that caused the skip has changed since.
+    fn recall(&self, file_path: &Path, destination: &str, options: &OrganizeOptions) -> Option<Organized> {
+        let mut skipped = self.skipped.lock().unwrap();
+        let key = (file_path.to_path_buf(), destination.to_string());
+        let remembered = skipped.get(&key)?;
+        if remembered.options == *options && skip_stamps(file_path, &remembered.organized) == Some(remembered.stamps) {
+            return Some(remembered.organized.clone());
+        }
+        skipped.remove(&key);
+        None
+    }
+
+    fn remember(&self, file_path: &Path, destination: &str, options: &OrganizeOptions, organized: &Organized) {
+        let Some(stamps) = skip_stamps(file_path, organized) else {
+            return;
         };
-        dest_file = dest_path.join(&new_name);
-        counter += 1;
+        self.skipped.lock().unwrap().insert(
+            (file_path.to_path_buf(), destination.to_string()),
+            SkippedFile {
+                options: options.clone(),
+                stamps,
+                organized: organized.clone(),
+            },
+        );
     }
 
-    fs::rename(file_path, &dest_file)
-        .map_err(|e| format!("Failed to move file: {}", e))?;
+    /// Drops what is remembered about `path`, which was removed or renamed.
+    pub fn forget(&self, path: &Path) {
+        self.skipped.lock().unwrap().retain(|(source, _), _| source != path);
+    }
+
+    /// Drops entries for files that no longer exist.
+    pub fn prune(&self) {
+        self.skipped.lock().unwrap().retain(|(source, _), _| source.exists());
+    }
+}
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = path.metadata().ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// The file whose presence made `organized` a skip.
+fn skip_cause(organized: &Organized) -> Option<&str> {
+    match &organized.conflict {
+        Some(conflict) => Some(&conflict.existing),
+        None => organized.duplicate_of.as_deref(),
+    }
+}
 
-    Ok(dest_file.to_string_lossy().to_string())
+fn skip_stamps(file_path: &Path, organized: &Organized) -> Option<(FileStamp, FileStamp)> {
+    Some((stamp(file_path)?, stamp(Path::new(skip_cause(organized)?))?))
 }
 
-pub fn organize_file_to_destination(file_path: &Path, destination: &str, new_name: Option<&str>) -> Result<String, String> {
+/// Places the file in `destination` as `file_name`, applying `policy` if a
+/// different file already has that name.
+fn place(
+    file_path: &Path,
+    destination: &str,
+    file_name: &str,
+    action: &RuleAction,
+    policy: ConflictPolicy,
+    progress: Option<Progress>,
+) -> Result<Placement, String> {
     let dest_path = PathBuf::from(destination);
-    if !dest_path.exists() {
-        fs::create_dir_all(&dest_path)
-            .map_err(|e| format!("Failed to create destination folder: {}", e))?;
+    create_folder(&dest_path)?;
+
+    // A `{counter}` name is numbered by design, so there's nothing to resolve
+    if file_name.contains(&format!("{{{}}}", COUNTER_PLACEHOLDER)) {
+        return Ok(Placement {
+            path: Some(place_under_free_name(file_path, &dest_path, file_name, action, progress)?),
+            conflict: None,
+        });
+    }
+
+    let preferred = dest_path.join(file_name);
+    match place_new(file_path, &preferred, action, progress) {
+        Ok(()) => {
+            return Ok(Placement {
+                path: Some(preferred),
+                conflict: None,
+            })
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(failure_message(action, &e)),
+    }
+
+    let mut backup = None;
+    let resolution = conflict_resolution(policy, file_path, &preferred);
+    let path = match resolution {
+        ConflictResolution::KeptBoth => {
+            let name = kept_name(policy, file_path, file_name);
+            Some(place_under_free_name(file_path, &dest_path, &name, action, progress)?)
+        }
+        ConflictResolution::Overwritten => {
+            backup = Some(overwrite(file_path, &preferred, file_name, action, progress)?);
+            Some(preferred.clone())
+        }
+        ConflictResolution::Skipped | ConflictResolution::Deferred => None,
+    };
+    Ok(Placement {
+        path,
+        conflict: Some(Conflict {
+            existing: preferred.to_string_lossy().to_string(),
+            resolution,
+            backup: backup.map(|b| b.to_string_lossy().to_string()),
+        }),
+    })
+}
+
+/// How `policy` settles a clash between the file and `existing`, which has its name.
+fn conflict_resolution(policy: ConflictPolicy, file_path: &Path, existing: &Path) -> ConflictResolution {
+    match policy {
+        ConflictPolicy::KeepBoth | ConflictPolicy::KeepBothTimestamp => ConflictResolution::KeptBoth,
+        ConflictPolicy::OverwriteIfNewer if is_newer(file_path, existing) => ConflictResolution::Overwritten,
+        ConflictPolicy::OverwriteIfNewer | ConflictPolicy::Skip => ConflictResolution::Skipped,
+        ConflictPolicy::Ask => ConflictResolution::Deferred,
+    }
+}
+
+/// The name numbered variants are tried for when both files are kept.
+fn kept_name(policy: ConflictPolicy, file_path: &Path, file_name: &str) -> String {
+    match policy {
+        ConflictPolicy::KeepBothTimestamp => timestamped_name(file_path, file_name),
+        _ => file_name.to_string(),
+    }
+}
+
+/// Where files replaced by `OverwriteIfNewer` are kept.
+pub fn replaced_files_dir() -> PathBuf {
+    Config::config_path().with_file_name("replaced")
+}
+
+/// Replaces `existing` with the file, first moving `existing` into
+/// `replaced_files_dir` rather than deleting it. Returns where it was moved.
+fn overwrite(
+    file_path: &Path,
+    existing: &Path,
+    file_name: &str,
+    action: &RuleAction,
+    progress: Option<Progress>,
+) -> Result<PathBuf, String> {
+    let backup = place_under_free_name(existing, &replaced_files_dir(), file_name, &RuleAction::Move, None)
+        .map_err(|e| format!("Failed to set aside the existing file: {}", e))?;
+    if let Err(e) = place_new(file_path, existing, action, progress) {
+        let _ = transfer::move_file_new(&backup, existing, None);
+        return Err(failure_message(action, &e));
+    }
+    Ok(backup)
+}
+
+/// Places the file under the first free name among `file_name`'s numbered
+/// variants. Each attempt fails atomically if the name is taken, so files placed
+/// concurrently under the same name never replace each other.
+fn place_under_free_name(
+    file_path: &Path,
+    dest_path: &Path,
+    file_name: &str,
+    action: &RuleAction,
+    progress: Option<Progress>,
+) -> Result<PathBuf, String> {
+    create_folder(dest_path)?;
+    for candidate in candidate_paths(dest_path, file_name) {
+        match place_new(file_path, &candidate, action, progress) {
+            Ok(()) => return Ok(candidate),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(failure_message(action, &e)),
+        }
+    }
+    Err("No free file name in the destination".to_string())
+}
+
+fn create_folder(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        fs::create_dir_all(path).map_err(|e| format!("Failed to create destination folder: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Moves, copies or links the file to `target`, failing with `AlreadyExists`
+/// rather than replacing anything there.
+fn place_new(file_path: &Path, target: &Path, action: &RuleAction, progress: Option<Progress>) -> io::Result<()> {
+    match action {
+        RuleAction::Move => transfer::move_file_new(file_path, target, progress),
+        RuleAction::Symlink => symlink_file(&file_path.canonicalize()?, target),
+        RuleAction::Hardlink => fs::hard_link(file_path, target),
+        RuleAction::Copy | RuleAction::MultiCopy { .. } => {
+            // Claim the name first; the copy then fills in the placeholder
+            fs::File::options().write(true).create_new(true).open(target)?;
+            fs::copy(file_path, target).map(|_| ()).inspect_err(|_| {
+                let _ = fs::remove_file(target);
+            })
+        }
+    }
+}
+
+fn failure_message(action: &RuleAction, error: &io::Error) -> String {
+    match action {
+        RuleAction::Move => format!("Failed to move file: {}", error),
+        RuleAction::Symlink => format!("Failed to create symlink: {}", error),
+        RuleAction::Hardlink => format!("Failed to create hardlink: {}", error),
+        RuleAction::Copy | RuleAction::MultiCopy { .. } => format!("Failed to copy file: {}", error),
+    }
+}
+
+/// `report.pdf` becomes e.g. `report 2024-05-01 14.03.22.pdf`, using the file's
+/// modification time.
+fn timestamped_name(file_path: &Path, file_name: &str) -> String {
+    let modified: DateTime<Local> = file_path
+        .metadata()
+        .and_then(|m| m.modified())
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Local::now());
+    let stamp = modified.format("%Y-%m-%d %H.%M.%S");
+    let name = Path::new(file_name);
+    let stem = name.file_stem().and_then(|s| s.to_str()).unwrap_or(file_name);
+    match name.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{} {}.{}", stem, stamp, ext),
+        None => format!("{} {}", stem, stamp),
+    }
+}
+
+fn is_newer(file_path: &Path, existing: &Path) -> bool {
+    let modified = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    match (modified(file_path), modified(existing)) {
+        (Some(incoming), Some(existing)) => incoming > existing,
+        _ => false,
+    }
+}
+
+/// Moves, copies or links the file to exactly `target`, replacing whatever is there.
+fn place_at(file_path: &Path, target: &Path, action: &RuleAction, progress: Option<Progress>) -> Result<(), String> {
+    if matches!(action, RuleAction::Symlink | RuleAction::Hardlink) && target.symlink_metadata().is_ok() {
+        fs::remove_file(target).map_err(|e| format!("Failed to replace existing file: {}", e))?;
+    }
+    match action {
+        RuleAction::Move => transfer::move_file(file_path, target, progress),
+        RuleAction::Symlink => {
+            let source = file_path
+                .canonicalize()
+                .map_err(|e| format!("Failed to resolve file path: {}", e))?;
+            symlink_file(&source, target).map_err(|e| format!("Failed to create symlink: {}", e))
+        }
+        RuleAction::Hardlink => {
+            fs::hard_link(file_path, target).map_err(|e| format!("Failed to create hardlink: {}", e))
+        }
+        RuleAction::Copy | RuleAction::MultiCopy { .. } => fs::copy(file_path, target)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to copy file: {}", e)),
+    }
+}
+
+/// Places the file at exactly `target` without recording it, for callers that keep
+/// their own history entry. If `target` is taken, ` (N)` is added to its name rather
+/// than replacing what is there. Moves work across volumes.
+pub fn place_unrecorded(file_path: &Path, target: &Path, action: &RuleAction) -> Result<PathBuf, String> {
+    let folder = target
+        .parent()
+        .ok_or_else(|| "Target has no parent folder".to_string())?;
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| "Invalid target file name".to_string())?;
+    create_folder(folder)?;
+    match place_new(file_path, target, action, None) {
+        Ok(()) => Ok(target.to_path_buf()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            place_under_free_name(file_path, folder, name, action, None)
+        }
+        Err(e) => Err(failure_message(action, &e)),
+    }
+}
+
+/// Whether `target` is already the result of applying `action` to `source`, so a
+/// sweep over files that a copy or link rule left in place doesn't repeat itself.
+pub fn already_placed(source: &Path, target: &Path, action: &RuleAction) -> bool {
+    match action {
+        RuleAction::Symlink => match (fs::read_link(target), source.canonicalize()) {
+            (Ok(link), Ok(source)) => link == source,
+            _ => false,
+        },
+        RuleAction::Hardlink => same_file(source, target),
+        _ => transfer::same_content(source, target),
     }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    transfer::same_content(a, b)
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// What organizing a file would do in one of its destinations.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlannedPlacement {
+    pub destination: String,
+    /// Where the file would be placed, or `None` if it would be left where it is.
+    pub path: Option<String>,
+    /// A file with identical content that is already in the destination.
+    pub duplicate_of: Option<String>,
+    pub conflict: Option<ConflictResolution>,
+}
 
-    let file_name_obj = if let Some(name) = new_name {
+/// What `organize_file_to_destination` would do in `destination` and in any
+/// `MultiCopy` extras, without touching the disk. `taken` reports paths that
+/// should count as occupied besides existing files, so a preview of several files
+/// can account for earlier ones.
+pub fn plan_placements<F>(
+    file_path: &Path,
+    destination: &str,
+    options: &OrganizeOptions,
+    taken: F,
+) -> Result<Vec<PlannedPlacement>, String>
+where
+    F: Fn(&Path) -> bool,
+{
+    let action = &options.action;
+    let file_name = target_file_name(file_path, options.new_name.as_deref())?;
+    let mut destinations = vec![destination];
+    if let RuleAction::MultiCopy { destinations: extra } = action {
+        destinations.extend(extra.iter().map(|d| d.as_str()));
+    }
+
+    let mut planned = Vec::new();
+    for destination in destinations {
+        let existing = Path::new(destination).join(&file_name);
+        if *action != RuleAction::Move && already_placed(file_path, &existing, action) {
+            planned.push(PlannedPlacement {
+                destination: destination.to_string(),
+                path: Some(existing.to_string_lossy().to_string()),
+                duplicate_of: None,
+                conflict: None,
+            });
+            continue;
+        }
+
+        let duplicate = match action {
+            RuleAction::Symlink | RuleAction::Hardlink => None,
+            _ => find_duplicate(file_path, Path::new(destination), &file_name),
+        };
+        let (path, conflict) = match (&duplicate, &options.on_duplicate) {
+            (Some(_), DuplicatePolicy::Skip) => (None, None),
+            (Some(existing), DuplicatePolicy::Replace) => (Some(existing.clone()), None),
+            (Some(_), DuplicatePolicy::DuplicatesFolder { folder }) => {
+                plan_in(file_path, Path::new(folder), &file_name, ConflictPolicy::KeepBoth, &taken)
+            }
+            _ => plan_in(file_path, Path::new(destination), &file_name, options.on_conflict, &taken),
+        };
+        planned.push(PlannedPlacement {
+            destination: destination.to_string(),
+            path: path.map(|p| p.to_string_lossy().to_string()),
+            duplicate_of: duplicate.map(|d| d.to_string_lossy().to_string()),
+            conflict,
+        });
+    }
+    Ok(planned)
+}
+
+/// The dry-run counterpart of `place`.
+fn plan_in<F>(
+    file_path: &Path,
+    dest_path: &Path,
+    file_name: &str,
+    policy: ConflictPolicy,
+    taken: &F,
+) -> (Option<PathBuf>, Option<ConflictResolution>)
+where
+    F: Fn(&Path) -> bool,
+{
+    let occupied = |p: &Path| p.symlink_metadata().is_ok() || taken(p);
+    let preferred = dest_path.join(file_name);
+    if file_name.contains(&format!("{{{}}}", COUNTER_PLACEHOLDER)) || !occupied(&preferred) {
+        return (Some(available_destination(dest_path, file_name, occupied)), None);
+    }
+
+    let resolution = conflict_resolution(policy, file_path, &preferred);
+    let path = match resolution {
+        ConflictResolution::KeptBoth => {
+            let name = kept_name(policy, file_path, file_name);
+            Some(available_destination(dest_path, &name, occupied))
+        }
+        ConflictResolution::Overwritten => Some(preferred),
+        ConflictResolution::Skipped | ConflictResolution::Deferred => None,
+    };
+    (path, Some(resolution))
+}
+
+fn target_file_name(file_path: &Path, new_name: Option<&str>) -> Result<String, String> {
+    if let Some(name) = new_name {
         // Use the provided new name, but preserve extension if not included
         let original_ext = file_path
             .extension()
@@ -78,42 +674,331 @@ pub fn organize_file_to_destination(file_path: &Path, destination: &str, new_nam
         
         if !original_ext.is_empty() && !name.contains('.') {
             // Add extension if not present in new name
-            format!("{}.{}", name, original_ext)
+            Ok(format!("{}.{}", name, original_ext))
         } else {
-            name.to_string()
+            Ok(name.to_string())
         }
     } else {
         file_path
             .file_name()
             .and_then(|n| n.to_str())
             .map(|s| s.to_string())
-            .ok_or_else(|| "Invalid file name".to_string())?
-    };
+            .ok_or_else(|| "Invalid file name".to_string())
+    }
+}
 
-    let mut dest_file = dest_path.join(&file_name_obj);
-    let mut counter = 1;
+/// Picks a path in `dest_path` for which `taken` is false, see `candidate_paths`.
+fn available_destination<F>(dest_path: &Path, file_name: &str, taken: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    candidate_paths(dest_path, file_name)
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_else(|| dest_path.join(file_name))
+}
 
-    while dest_file.exists() {
-        let stem = dest_file
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("file");
-        let ext = dest_file
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("");
+/// The names a file may take in `dest_path`, in order of preference. A `{counter}`
+/// in the name is replaced with 1, 2, …; otherwise the name itself comes first,
+/// followed by ` (N)` appended to the stem.
+fn candidate_paths<'a>(dest_path: &'a Path, file_name: &'a str) -> Box<dyn Iterator<Item = PathBuf> + 'a> {
+    let counter_placeholder = format!("{{{}}}", COUNTER_PLACEHOLDER);
+    if file_name.contains(&counter_placeholder) {
+        return Box::new(
+            (1u64..).map(move |counter| dest_path.join(file_name.replace(&counter_placeholder, &counter.to_string()))),
+        );
+    }
+
+    let original = Path::new(file_name);
+    let stem = original
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("file");
+    let ext = original
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+    let numbered = (1u64..).map(move |counter| {
         let new_name = if ext.is_empty() {
             format!("{} ({})", stem, counter)
         } else {
             format!("{} ({}).{}", stem, counter, ext)
         };
-        dest_file = dest_path.join(&new_name);
-        counter += 1;
+        dest_path.join(new_name)
+    });
+    Box::new(std::iter::once(dest_path.join(file_name)).chain(numbered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    const THREADS: usize = 8;
+    const FILES_PER_THREAD: usize = 40;
+
+    /// Places THREADS * FILES_PER_THREAD files with distinct content, all named
+    /// `file_name`, into one folder from parallel threads, and checks that every
+    /// one of them survives.
+    fn place_same_name_in_parallel(label: &str, file_name: &str, action: RuleAction) {
+        let root = scratch_dir(label);
+        let destination = root.join("destination");
+        let barrier = Arc::new(Barrier::new(THREADS));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let root = root.clone();
+                let destination = destination.clone();
+                let barrier = barrier.clone();
+                let action = action.clone();
+                let file_name = file_name.to_string();
+                thread::spawn(move || {
+                    let sources: Vec<PathBuf> = (0..FILES_PER_THREAD)
+                        .map(|i| {
+                            let dir = root.join(format!("source-{}-{}", t, i));
+                            fs::create_dir_all(&dir).unwrap();
+                            let source = dir.join("report.pdf");
+                            fs::write(&source, format!("thread {} file {}", t, i)).unwrap();
+                            source
+                        })
+                        .collect();
+                    barrier.wait();
+                    for source in sources {
+                        let placement = place(
+                            &source,
+                            &destination.to_string_lossy(),
+                            &file_name,
+                            &action,
+                            ConflictPolicy::KeepBoth,
+                            None,
+                        )
+                        .unwrap();
+                        assert!(placement.path.is_some());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let contents: HashSet<String> = fs::read_dir(&destination)
+            .unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        assert_eq!(contents.len(), THREADS * FILES_PER_THREAD);
+        let sources_left = (0..THREADS)
+            .flat_map(|t| (0..FILES_PER_THREAD).map(move |i| (t, i)))
+            .filter(|(t, i)| root.join(format!("source-{}-{}/report.pdf", t, i)).exists())
+            .count();
+        let expected_left = if action == RuleAction::Move { 0 } else { THREADS * FILES_PER_THREAD };
+        assert_eq!(sources_left, expected_left);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn parallel_moves_never_clobber() {
+        place_same_name_in_parallel("move", "report.pdf", RuleAction::Move);
+    }
+
+    #[test]
+    fn parallel_copies_never_clobber() {
+        place_same_name_in_parallel("copy", "report.pdf", RuleAction::Copy);
+    }
+
+    #[test]
+    fn parallel_counter_names_never_clobber() {
+        place_same_name_in_parallel("counter", "scan-{counter}.pdf", RuleAction::Move);
+    }
+
+    #[test]
+    fn plan_applies_policies_in_every_destination() {
+        let root = scratch_dir("plan");
+        let source = root.join("report.pdf");
+        fs::write(&source, "new report").unwrap();
+        let (first, second, third) = (root.join("first"), root.join("second"), root.join("third"));
+        for dir in [&first, &second, &third] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        // Identical content under another name, and a different file with the same name
+        fs::write(second.join("copy.pdf"), "new report").unwrap();
+        fs::write(third.join("report.pdf"), "old report").unwrap();
+
+        let options = OrganizeOptions {
+            action: RuleAction::MultiCopy {
+                destinations: vec![second.to_string_lossy().to_string(), third.to_string_lossy().to_string()],
+            },
+            on_duplicate: DuplicatePolicy::Skip,
+            on_conflict: ConflictPolicy::KeepBoth,
+            ..Default::default()
+        };
+        let claimed = first.join("report.pdf");
+        let planned = plan_placements(&source, &first.to_string_lossy(), &options, |p| p == claimed).unwrap();
+
+        let path = |p: PathBuf| Some(p.to_string_lossy().to_string());
+        assert_eq!(planned[0].path, path(first.join("report (1).pdf")));
+        assert_eq!(planned[1].path, None);
+        assert_eq!(planned[1].duplicate_of, path(second.join("copy.pdf")));
+        assert_eq!(planned[2].path, path(third.join("report (1).pdf")));
+        assert_eq!(planned[2].conflict, Some(ConflictResolution::KeptBoth));
+        // Nothing was placed
+        assert_eq!(fs::read_dir(&first).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(&second).unwrap().count(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
     }
 
-    fs::rename(file_path, &dest_file)
-        .map_err(|e| format!("Failed to move file: {}", e))?;
+    #[test]
+    fn skip_memory_only_recalls_the_same_options() {
+        let root = scratch_dir("skips");
+        let source = root.join("report.pdf");
+        let existing = root.join("destination").join("report.pdf");
+        fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        fs::write(&source, "report").unwrap();
+        fs::write(&existing, "report").unwrap();
+        let destination = existing.parent().unwrap().to_string_lossy().to_string();
+        let skip = OrganizeOptions {
+            on_duplicate: DuplicatePolicy::Skip,
+            ..Default::default()
+        };
+        let organized = Organized {
+            path: source.to_string_lossy().to_string(),
+            action: HistoryAction::Skipped,
+            duplicate_of: Some(existing.to_string_lossy().to_string()),
+            conflict: None,
+        };
+
+        let skips = SkipMemory::default();
+        skips.remember(&source, &destination, &skip, &organized);
+        assert_eq!(skips.recall(&source, &destination, &skip), Some(organized.clone()));
+        assert_eq!(skips.recall(&source, &destination, &OrganizeOptions::default()), None);
+        // A miss forgets the file, so the next placement is decided afresh
+        assert_eq!(skips.recall(&source, &destination, &skip), None);
 
-    Ok(dest_file.to_string_lossy().to_string())
-}
+        skips.remember(&source, &destination, &skip, &organized);
+        fs::remove_file(&source).unwrap();
+        skips.prune();
+        assert!(skips.skipped.lock().unwrap().is_empty());
 
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// A source and a different file already at its name in `destination`, the
+    /// source modified `source_age` and the existing file `existing_age` ago.
+    fn conflicting_files(label: &str, source_age: u64, existing_age: u64) -> (PathBuf, PathBuf, PathBuf) {
+        let root = scratch_dir(label);
+        let source = root.join("report.pdf");
+        let existing = root.join("destination").join("report.pdf");
+        fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        fs::write(&source, "new report").unwrap();
+        fs::write(&existing, "old report").unwrap();
+        let now = SystemTime::now();
+        for (path, age) in [(&source, source_age), (&existing, existing_age)] {
+            let modified = now - std::time::Duration::from_secs(age);
+            fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+        }
+        (root, source, existing)
+    }
+
+    fn place_with(source: &Path, existing: &Path, policy: ConflictPolicy) -> Placement {
+        let destination = existing.parent().unwrap().to_string_lossy().to_string();
+        place(source, &destination, "report.pdf", &RuleAction::Move, policy, None).unwrap()
+    }
+
+    #[test]
+    fn keep_both_timestamp_adds_the_modification_time() {
+        let (root, source, existing) = conflicting_files("conflict-timestamp", 0, 60);
+        let stamp = timestamped_name(&source, "report.pdf");
+        let placement = place_with(&source, &existing, ConflictPolicy::KeepBothTimestamp);
+
+        let placed = existing.with_file_name(&stamp);
+        assert!(stamp.starts_with("report 2") && stamp.ends_with(".pdf"), "{}", stamp);
+        assert_eq!(placement.path, Some(placed.clone()));
+        assert_eq!(placement.conflict.unwrap().resolution, ConflictResolution::KeptBoth);
+        assert_eq!(fs::read_to_string(&placed).unwrap(), "new report");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old report");
+        assert!(!source.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn overwrite_if_newer_sets_the_old_file_aside() {
+        let (root, source, existing) = conflicting_files("conflict-newer", 0, 60);
+        let placement = place_with(&source, &existing, ConflictPolicy::OverwriteIfNewer);
+
+        let conflict = placement.conflict.unwrap();
+        assert_eq!(placement.path, Some(existing.clone()));
+        assert_eq!(conflict.resolution, ConflictResolution::Overwritten);
+        let backup = PathBuf::from(conflict.backup.unwrap());
+        assert!(backup.starts_with(replaced_files_dir()));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old report");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new report");
+        assert!(!source.exists());
+
+        fs::remove_file(&backup).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn overwrite_if_newer_leaves_an_older_file_in_place() {
+        let (root, source, existing) = conflicting_files("conflict-older", 60, 0);
+        let placement = place_with(&source, &existing, ConflictPolicy::OverwriteIfNewer);
+
+        let conflict = placement.conflict.unwrap();
+        assert_eq!(placement.path, None);
+        assert_eq!(conflict.resolution, ConflictResolution::Skipped);
+        assert_eq!(conflict.backup, None);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "new report");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old report");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn skip_leaves_both_files() {
+        let (root, source, existing) = conflicting_files("conflict-skip", 0, 60);
+        let placement = place_with(&source, &existing, ConflictPolicy::Skip);
+
+        assert_eq!(placement.path, None);
+        assert_eq!(placement.conflict.unwrap().resolution, ConflictResolution::Skipped);
+        assert_eq!(fs::read_to_string(&source).unwrap(), "new report");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old report");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn ask_defers_without_recording_history() {
+        let (root, source, existing) = conflicting_files("conflict-ask", 0, 60);
+        let options = OrganizeOptions {
+            on_conflict: ConflictPolicy::Ask,
+            ..Default::default()
+        };
+        let destination = existing.parent().unwrap().to_string_lossy().to_string();
+        let organized = organize_file_to_destination(&source, &destination, &options, None, None).unwrap();
+
+        assert!(organized.is_deferred());
+        assert_eq!(organized.path, source.to_string_lossy());
+        assert_eq!(fs::read_to_string(&source).unwrap(), "new report");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "old report");
+        let recorded = |source: &Path| {
+            let source = source.to_string_lossy();
+            history::load_all().into_iter().filter(|entry| entry.source == source).count()
+        };
+        assert_eq!(recorded(&source), 0);
+
+        // The same file skipped instead is recorded
+        let options = OrganizeOptions {
+            on_conflict: ConflictPolicy::Skip,
+            ..Default::default()
+        };
+        organize_file_to_destination(&source, &destination, &options, None, None).unwrap();
+        assert_eq!(recorded(&source), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,440 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Broad category of a file, used by the `kind` rule condition.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    Image,
+    Archive,
+    Document,
+    Audio,
+    Video,
+    Other,
+}
+
+impl FileKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::Image => "image",
+            FileKind::Archive => "archive",
+            FileKind::Document => "document",
+            FileKind::Audio => "audio",
+            FileKind::Video => "video",
+            FileKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileType {
+    pub mime: &'static str,
+    pub kind: FileKind,
+    /// Extensions this type is saved with; the first one is canonical.
+    pub extensions: &'static [&'static str],
+}
+
+const fn file_type(mime: &'static str, kind: FileKind, extensions: &'static [&'static str]) -> FileType {
+    FileType { mime, kind, extensions }
+}
+
+const PNG: FileType = file_type("image/png", FileKind::Image, &["png"]);
+const JPEG: FileType = file_type("image/jpeg", FileKind::Image, &["jpg", "jpeg", "jpe"]);
+const GIF: FileType = file_type("image/gif", FileKind::Image, &["gif"]);
+const WEBP: FileType = file_type("image/webp", FileKind::Image, &["webp"]);
+const BMP: FileType = file_type("image/bmp", FileKind::Image, &["bmp"]);
+const TIFF: FileType = file_type("image/tiff", FileKind::Image, &["tiff", "tif", "dng", "cr2", "nef", "arw"]);
+const ICO: FileType = file_type("image/x-icon", FileKind::Image, &["ico"]);
+const HEIC: FileType = file_type("image/heic", FileKind::Image, &["heic", "heif"]);
+const AVIF: FileType = file_type("image/avif", FileKind::Image, &["avif"]);
+const PSD: FileType = file_type("image/vnd.adobe.photoshop", FileKind::Image, &["psd"]);
+
+const ZIP: FileType = file_type("application/zip", FileKind::Archive, &["zip", "jar", "apk", "xpi", "ipa", "whl", "nupkg"]);
+const RAR: FileType = file_type("application/vnd.rar", FileKind::Archive, &["rar"]);
+const SEVEN_ZIP: FileType = file_type("application/x-7z-compressed", FileKind::Archive, &["7z"]);
+const GZIP: FileType = file_type("application/gzip", FileKind::Archive, &["gz", "tgz"]);
+const BZIP2: FileType = file_type("application/x-bzip2", FileKind::Archive, &["bz2", "tbz2"]);
+const XZ: FileType = file_type("application/x-xz", FileKind::Archive, &["xz", "txz"]);
+const ZSTD: FileType = file_type("application/zstd", FileKind::Archive, &["zst"]);
+const TAR: FileType = file_type("application/x-tar", FileKind::Archive, &["tar"]);
+const ISO: FileType = file_type("application/x-iso9660-image", FileKind::Archive, &["iso"]);
+
+const PDF: FileType = file_type("application/pdf", FileKind::Document, &["pdf"]);
+const RTF: FileType = file_type("application/rtf", FileKind::Document, &["rtf"]);
+const OLE: FileType = file_type("application/x-ole-storage", FileKind::Document, &["doc", "xls", "ppt", "msg"]);
+const DOCX: FileType = file_type(
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    FileKind::Document,
+    &["docx", "docm"],
+);
+const XLSX: FileType = file_type(
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    FileKind::Document,
+    &["xlsx", "xlsm"],
+);
+const PPTX: FileType = file_type(
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    FileKind::Document,
+    &["pptx", "pptm"],
+);
+const EPUB: FileType = file_type("application/epub+zip", FileKind::Document, &["epub"]);
+const ODF: FileType = file_type("application/vnd.oasis.opendocument.text", FileKind::Document, &["odt", "ods", "odp"]);
+
+const MP3: FileType = file_type("audio/mpeg", FileKind::Audio, &["mp3"]);
+const FLAC: FileType = file_type("audio/flac", FileKind::Audio, &["flac"]);
+const WAV: FileType = file_type("audio/wav", FileKind::Audio, &["wav"]);
+const OGG: FileType = file_type("audio/ogg", FileKind::Audio, &["ogg", "oga", "opus"]);
+const M4A: FileType = file_type("audio/mp4", FileKind::Audio, &["m4a", "m4b"]);
+const AIFF: FileType = file_type("audio/aiff", FileKind::Audio, &["aiff", "aif"]);
+const MIDI: FileType = file_type("audio/midi", FileKind::Audio, &["mid", "midi"]);
+
+const MP4: FileType = file_type("video/mp4", FileKind::Video, &["mp4", "m4v"]);
+const MOV: FileType = file_type("video/quicktime", FileKind::Video, &["mov"]);
+const MKV: FileType = file_type("video/x-matroska", FileKind::Video, &["mkv"]);
+const WEBM: FileType = file_type("video/webm", FileKind::Video, &["webm"]);
+const AVI: FileType = file_type("video/x-msvideo", FileKind::Video, &["avi"]);
+const FLV: FileType = file_type("video/x-flv", FileKind::Video, &["flv"]);
+const MPEG: FileType = file_type("video/mpeg", FileKind::Video, &["mpg", "mpeg"]);
+const THREE_GP: FileType = file_type("video/3gpp", FileKind::Video, &["3gp"]);
+
+/// Every type the sniffer can report, used to look types up by extension.
+const SNIFFED_TYPES: &[FileType] = &[
+    PNG, JPEG, GIF, WEBP, BMP, TIFF, ICO, HEIC, AVIF, PSD, ZIP, RAR, SEVEN_ZIP, GZIP, BZIP2, XZ,
+    ZSTD, TAR, ISO, PDF, RTF, OLE, DOCX, XLSX, PPTX, EPUB, ODF, MP3, FLAC, WAV, OGG, M4A,
+    AIFF, MIDI, MP4, MOV, MKV, WEBM, AVI, FLV, MPEG, THREE_GP,
+];
+
+/// Formats without a leading signature, which are only recognized by extension.
+const EXTENSION_ONLY_TYPES: &[FileType] = &[
+    file_type("image/svg+xml", FileKind::Image, &["svg"]),
+    file_type("text/plain", FileKind::Document, &["txt", "md", "log"]),
+    file_type("text/csv", FileKind::Document, &["csv", "tsv"]),
+    file_type("text/html", FileKind::Document, &["html", "htm"]),
+    file_type("application/json", FileKind::Document, &["json"]),
+    file_type("application/vnd.apple.pages", FileKind::Document, &["pages", "numbers", "key"]),
+    file_type("audio/aac", FileKind::Audio, &["aac"]),
+    // Disk images keep their signature in a trailer at the end of the file
+    file_type("application/x-apple-diskimage", FileKind::Archive, &["dmg"]),
+];
+
+/// Extensions that describe how a file was served rather than what it contains.
+const UNINFORMATIVE_EXTENSIONS: &[&str] = &[
+    "php", "php3", "asp", "aspx", "jsp", "cgi", "do", "action", "htm", "html", "bin", "dat", "file",
+];
+
+/// How many bytes are read from the start of a file for sniffing.
+const SNIFF_LEN: usize = 8192;
+
+/// Identifies a file from its leading bytes. Returns `None` for text files and
+/// anything not in the table.
+pub fn sniff(path: &Path) -> Option<FileType> {
+    let mut file = File::open(path).ok()?;
+    let mut buffer = Vec::with_capacity(SNIFF_LEN);
+    file.by_ref().take(SNIFF_LEN as u64).read_to_end(&mut buffer).ok()?;
+    if let Some(detected) = sniff_bytes(&buffer) {
+        return Some(detected);
+    }
+    // ISO 9660 keeps its signature at 32 KiB, past the first read
+    let mut volume_descriptor = [0u8; 5];
+    file.seek(SeekFrom::Start(0x8001)).ok()?;
+    file.read_exact(&mut volume_descriptor).ok()?;
+    if &volume_descriptor == b"CD001" {
+        return Some(ISO);
+    }
+    None
+}
+
+pub fn sniff_bytes(bytes: &[u8]) -> Option<FileType> {
+    let starts = |magic: &[u8]| bytes.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| bytes.get(offset..offset + magic.len()) == Some(magic);
+
+    let detected = if starts(b"\x89PNG\r\n\x1a\n") {
+        PNG
+    } else if starts(b"\xff\xd8\xff") {
+        JPEG
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        GIF
+    } else if starts(b"RIFF") && at(8, b"WEBP") {
+        WEBP
+    } else if starts(b"RIFF") && at(8, b"WAVE") {
+        WAV
+    } else if starts(b"RIFF") && at(8, b"AVI ") {
+        AVI
+    } else if starts(b"BM") && bytes.len() > 14 && at(6, b"\0\0\0\0") {
+        BMP
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        TIFF
+    } else if starts(b"\0\0\x01\0") && bytes.len() > 6 && bytes[4] != 0 {
+        ICO
+    } else if starts(b"8BPS") {
+        PSD
+    } else if at(4, b"ftyp") {
+        return sniff_iso_media(bytes);
+    } else if starts(b"PK\x03\x04") {
+        sniff_zip(bytes)
+    } else if starts(b"Rar!\x1a\x07") {
+        RAR
+    } else if starts(b"7z\xbc\xaf\x27\x1c") {
+        SEVEN_ZIP
+    } else if starts(b"\x1f\x8b") {
+        GZIP
+    } else if starts(b"BZh") {
+        BZIP2
+    } else if starts(b"\xfd7zXZ\0") {
+        XZ
+    } else if starts(b"\x28\xb5\x2f\xfd") {
+        ZSTD
+    } else if at(257, b"ustar") {
+        TAR
+    } else if starts(b"%PDF-") {
+        PDF
+    } else if starts(b"{\\rtf") {
+        RTF
+    } else if starts(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1") {
+        OLE
+    } else if starts(b"ID3") || (bytes.len() > 1 && bytes[0] == 0xff && bytes[1] & 0xe6 == 0xe2) {
+        // ID3 tag, or an MPEG audio layer III frame sync
+        MP3
+    } else if starts(b"fLaC") {
+        FLAC
+    } else if starts(b"OggS") {
+        OGG
+    } else if starts(b"FORM") && (at(8, b"AIFF") || at(8, b"AIFC")) {
+        AIFF
+    } else if starts(b"MThd") {
+        MIDI
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        // Matroska and WebM share the EBML header; the doctype tells them apart
+        if bytes.windows(4).take(64).any(|w| w == b"webm") {
+            WEBM
+        } else {
+            MKV
+        }
+    } else if starts(b"FLV\x01") {
+        FLV
+    } else if starts(b"\0\0\x01\xba") || starts(b"\0\0\x01\xb3") {
+        MPEG
+    } else {
+        return None;
+    };
+    Some(detected)
+}
+
+/// ISO base media files (MP4, MOV, HEIC, ...) are told apart by their `ftyp` brand.
+fn sniff_iso_media(bytes: &[u8]) -> Option<FileType> {
+    let brand = bytes.get(8..12)?;
+    let detected = match brand {
+        b"heic" | b"heix" | b"hevc" | b"heim" | b"heis" | b"mif1" | b"msf1" => HEIC,
+        b"avif" | b"avis" => AVIF,
+        b"M4A " | b"M4B " | b"F4A " => M4A,
+        b"qt  " => MOV,
+        b"3gp4" | b"3gp5" | b"3gp6" | b"3g2a" => THREE_GP,
+        _ => MP4,
+    };
+    Some(detected)
+}
+
+/// Office Open XML, OpenDocument and EPUB files are ZIP archives; their first
+/// entries give them away.
+fn sniff_zip(bytes: &[u8]) -> FileType {
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    if contains(b"mimetypeapplication/epub+zip") {
+        EPUB
+    } else if contains(b"mimetypeapplication/vnd.oasis.opendocument") {
+        ODF
+    } else if contains(b"word/") {
+        DOCX
+    } else if contains(b"xl/") {
+        XLSX
+    } else if contains(b"ppt/") {
+        PPTX
+    } else {
+        ZIP
+    }
+}
+
+pub fn type_for_extension(extension: &str) -> Option<FileType> {
+    let extension = extension.to_lowercase();
+    SNIFFED_TYPES
+        .iter()
+        .chain(EXTENSION_ONLY_TYPES)
+        .find(|t| t.extensions.contains(&extension.as_str()))
+        .copied()
+}
+
+/// The file's type from its content, falling back to its extension.
+pub fn detect(path: &Path) -> Option<FileType> {
+    sniff(path).or_else(|| {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(type_for_extension)
+    })
+}
+
+/// The lowercase extension rules should see for `path`.
+///
+/// Files without an extension, or with one that says how they were served rather
+/// than what they are (`image.php`, `file.bin`), get the canonical extension of
+/// their sniffed type. Any other extension is trusted as-is.
+pub fn effective_extension(path: &Path) -> String {
+    let actual = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|e| e.to_lowercase());
+
+    let uninformative = actual
+        .as_deref()
+        .map(|ext| UNINFORMATIVE_EXTENSIONS.contains(&ext))
+        .unwrap_or(true);
+    if uninformative {
+        if let Some(detected) = sniff(path) {
+            return detected.extensions[0].to_string();
+        }
+    }
+
+    actual.unwrap_or_else(|| "other".to_string())
+}
+
+/// Matches a MIME type against a pattern such as `image/png` or `image/*`.
+pub fn mime_matches(pattern: &str, mime: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    match pattern.strip_suffix("/*") {
+        Some(prefix) => mime.split('/').next() == Some(prefix),
+        None => pattern == "*" || pattern == mime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use std::fs;
+
+    /// `magic` at `offset`, zero-padded to a realistic header length.
+    fn header(offset: usize, magic: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 512.max(offset + magic.len())];
+        bytes[offset..offset + magic.len()].copy_from_slice(magic);
+        bytes
+    }
+
+    fn joined(parts: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = parts.concat();
+        bytes.resize(bytes.len().max(512), 0);
+        bytes
+    }
+
+    #[test]
+    fn recognizes_every_signature() {
+        let cases: Vec<(Vec<u8>, FileType)> = vec![
+            (header(0, b"\x89PNG\r\n\x1a\n"), PNG),
+            (header(0, b"\xff\xd8\xff\xe0"), JPEG),
+            (header(0, b"GIF87a"), GIF),
+            (header(0, b"GIF89a"), GIF),
+            (joined(&[b"RIFF\0\0\0\0WEBP"]), WEBP),
+            (joined(&[b"RIFF\0\0\0\0WAVE"]), WAV),
+            (joined(&[b"RIFF\0\0\0\0AVI "]), AVI),
+            (joined(&[b"BM\x36\0\x0c\0\0\0\0\0\x36\0\0\0\x28"]), BMP),
+            (header(0, b"II*\0"), TIFF),
+            (header(0, b"MM\0*"), TIFF),
+            (joined(&[b"\0\0\x01\0\x01\0\x10\x10"]), ICO),
+            (header(0, b"8BPS"), PSD),
+            (joined(&[b"\0\0\0\x18ftypheic"]), HEIC),
+            (joined(&[b"\0\0\0\x18ftypmif1"]), HEIC),
+            (joined(&[b"\0\0\0\x18ftypavif"]), AVIF),
+            (joined(&[b"\0\0\0\x18ftypM4A "]), M4A),
+            (joined(&[b"\0\0\0\x14ftypqt  "]), MOV),
+            (joined(&[b"\0\0\0\x18ftyp3gp5"]), THREE_GP),
+            (joined(&[b"\0\0\0\x18ftypisom"]), MP4),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"mimetypeapplication/epub+zip"]), EPUB),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"mimetypeapplication/vnd.oasis.opendocument.text"]), ODF),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"word/document.xml"]), DOCX),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"xl/workbook.xml"]), XLSX),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"ppt/presentation.xml"]), PPTX),
+            (joined(&[b"PK\x03\x04", b"\0\0", b"readme.txt"]), ZIP),
+            (header(0, b"Rar!\x1a\x07\x01\0"), RAR),
+            (header(0, b"7z\xbc\xaf\x27\x1c"), SEVEN_ZIP),
+            (header(0, b"\x1f\x8b\x08"), GZIP),
+            (header(0, b"BZh9"), BZIP2),
+            (header(0, b"\xfd7zXZ\0"), XZ),
+            (header(0, b"\x28\xb5\x2f\xfd"), ZSTD),
+            (header(257, b"ustar"), TAR),
+            (header(0, b"%PDF-1.7"), PDF),
+            (header(0, b"{\\rtf1"), RTF),
+            (header(0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1"), OLE),
+            (header(0, b"ID3\x04"), MP3),
+            (header(0, b"\xff\xfb\x90"), MP3),
+            (header(0, b"fLaC"), FLAC),
+            (header(0, b"OggS"), OGG),
+            (joined(&[b"FORM\0\0\0\0AIFF"]), AIFF),
+            (joined(&[b"FORM\0\0\0\0AIFC"]), AIFF),
+            (header(0, b"MThd"), MIDI),
+            (joined(&[b"\x1a\x45\xdf\xa3", b"\x42\x82\x84webm"]), WEBM),
+            (joined(&[b"\x1a\x45\xdf\xa3", b"\x42\x82\x88matroska"]), MKV),
+            (header(0, b"FLV\x01"), FLV),
+            (header(0, b"\0\0\x01\xba"), MPEG),
+            (header(0, b"\0\0\x01\xb3"), MPEG),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(sniff_bytes(&bytes), Some(expected), "{:?}", &bytes[..16]);
+        }
+    }
+
+    #[test]
+    fn truncated_or_unknown_headers_are_not_recognized() {
+        let cases: &[&[u8]] = &[
+            b"",
+            b"\x89PN",
+            b"GIF8",
+            b"RIFF\0\0\0\0WE",
+            b"BM\x36\0",
+            b"\0\0\0\x18ftyp",
+            b"\0\0\x01\0\x01",
+            b"PK\x03",
+            b"%PDF",
+            b"hello, world",
+        ];
+        for bytes in cases {
+            assert_eq!(sniff_bytes(bytes), None, "{:?}", bytes);
+        }
+        // The tar magic sits at offset 257, so a short file can't be a tar
+        assert_eq!(sniff_bytes(&[0u8; 260]), None);
+    }
+
+    #[test]
+    fn finds_iso_signature_past_the_first_read() {
+        let dir = scratch_dir("file-type-iso");
+        let image = dir.join("disc");
+        let mut bytes = vec![0u8; 0x8010];
+        bytes[0x8001..0x8006].copy_from_slice(b"CD001");
+        fs::write(&image, bytes).unwrap();
+        assert_eq!(sniff(&image), Some(ISO));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn real_extension_wins_over_sniffed_type() {
+        let dir = scratch_dir("file-type-extension");
+        let png = header(0, b"\x89PNG\r\n\x1a\n");
+        let cases = [
+            ("photo.jpg", "jpg"),
+            ("Photo.JPG", "jpg"),
+            ("scan", "png"),
+            ("image.php", "png"),
+            ("download.BIN", "png"),
+        ];
+        for (name, expected) in cases {
+            let path = dir.join(name);
+            fs::write(&path, &png).unwrap();
+            assert_eq!(effective_extension(&path), expected, "{}", name);
+        }
+        let notes = dir.join("notes");
+        fs::write(&notes, "plain text").unwrap();
+        assert_eq!(effective_extension(&notes), "other");
+        assert_eq!(detect(&dir.join("photo.jpg")), Some(PNG));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,189 +1,93 @@
-use crate::config::{Config, PendingFile};
-use crate::file_organizer::organize_file_to_destination;
+use crate::config::{normalize_folder, Config, ConflictPolicy, PendingFile};
+use crate::events::WatcherEvent;
+use crate::file_organizer::{organize_file_to_destination, OrganizeOptions, SkipMemory};
+use crate::history::{self, HistoryAction, HistoryEntry};
+use crate::pending::{self, PendingQueue};
+use crate::routing::classify_and_route;
+use crate::settle::{self, SettleOptions, SettleOutcome, SettleTracker, SettlingFile};
+use crate::worker_pool::{panic_message, WorkerPool};
+use notify::event::{ModifyKind, RenameMode};
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
-use std::time::SystemTime;
+
+/// Number of files organized at the same time.
+const WORKER_COUNT: usize = 4;
+
+/// How often settling files are checked again.
+const SETTLE_TICK: Duration = Duration::from_millis(250);
 
 pub struct FileWatcher {
     watcher: RecommendedWatcher,
     config: Arc<Mutex<Config>>,
-    event_tx: broadcast::Sender<String>,
-    pending_files: Arc<Mutex<Vec<PendingFile>>>,
-    watched_path: Arc<Mutex<Option<std::path::PathBuf>>>,
+    event_tx: broadcast::Sender<WatcherEvent>,
+    pending_files: Arc<PendingQueue>,
+    settling_files: Arc<Mutex<Vec<SettlingFile>>>,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+    skips: Arc<SkipMemory>,
+    signal_tx: mpsc::Sender<PathSignal>,
+    watched_paths: Arc<Mutex<Vec<(PathBuf, RecursiveMode)>>>,
+    sweep_running: Arc<AtomicBool>,
+    sweep_cancelled: Arc<AtomicBool>,
+}
+
+/// Clears a flag when dropped, including while unwinding from a panic.
+struct ClearOnDrop(Arc<AtomicBool>);
+
+impl Drop for ClearOnDrop {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A filesystem event reduced to what it means for a single path.
+enum PathSignal {
+    Created(PathBuf),
+    Written(PathBuf),
+    Removed(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
 }
 
 impl FileWatcher {
-    pub fn new(event_tx: broadcast::Sender<String>) -> Result<Self, String> {
+    pub fn new(event_tx: broadcast::Sender<WatcherEvent>) -> Result<Self, String> {
         let config = Arc::new(Mutex::new(Config::load()));
-        let pending_files = Arc::new(Mutex::new(Vec::<PendingFile>::new()));
-        let config_clone = config.clone();
-        let pending_files_clone = pending_files.clone();
-        let event_tx_clone = event_tx.clone();
+        let pending_files = Arc::new(PendingQueue::load());
+        let settling_files = Arc::new(Mutex::new(Vec::<SettlingFile>::new()));
+        let in_flight = Arc::new(Mutex::new(HashSet::new()));
+        let skips = Arc::new(SkipMemory::default());
+        let (signal_tx, signal_rx) = mpsc::channel();
+
+        let coalescer = Coalescer {
+            config: config.clone(),
+            pending_files: pending_files.clone(),
+            settling_files: settling_files.clone(),
+            in_flight: in_flight.clone(),
+            skips: skips.clone(),
+            event_tx: event_tx.clone(),
+            pool: WorkerPool::new(WORKER_COUNT),
+            tracked: HashMap::new(),
+            timed_out: HashSet::new(),
+            open_checks: mpsc::channel(),
+        };
+        std::thread::spawn(move || coalescer.run(signal_rx));
 
+        let signal_tx_clone = signal_tx.clone();
+        let event_tx_clone = event_tx.clone();
         let watcher = notify::recommended_watcher(move |result: Result<Event, notify::Error>| {
             match result {
                 Ok(event) => {
-                    // Watch for Create and Modify events (downloads often create temp files then rename)
-                    // We'll process any Create event, and Modify events that might indicate file completion
-                    let should_process = match &event.kind {
-                        EventKind::Create(_) => true,
-                        EventKind::Modify(kind) => {
-                            // Process rename events (downloads often rename temp files)
-                            matches!(kind, notify::event::ModifyKind::Name(_))
-                        }
-                        _ => false,
-                    };
-                    if should_process {
-                        for path in event.paths {
-                            // Skip .download files and other temporary download files
-                            if let Some(ext) = path.extension() {
-                                if ext == "download" || ext == "crdownload" || ext == "part" {
-                                    continue;
-                                }
-                            }
-                            
-                            let path_clone = path.clone();
-                            let config_clone2 = config_clone.clone();
-                            let pending_files_clone2 = pending_files_clone.clone();
-                            let event_tx_clone2 = event_tx_clone.clone();
-                            
-                            std::thread::spawn(move || {
-                                // Wait a bit longer for downloads to complete
-                                std::thread::sleep(std::time::Duration::from_millis(500));
-                                // Check if it's a file after the delay
-                                if path_clone.exists() && path_clone.is_file() {
-                                        let config = config_clone2.lock().unwrap();
-                                        let mode = config.organization_mode.as_str();
-                                        
-                                        match mode {
-                                            "auto" => {
-                                                let extension_str = path_clone
-                                                    .extension()
-                                                    .and_then(|ext| ext.to_str())
-                                                    .map(|e| e.to_lowercase())
-                                                    .unwrap_or_else(|| "other".to_string());
-                                                
-                                                let file_name = path_clone
-                                                    .file_name()
-                                                    .and_then(|n| n.to_str())
-                                                    .unwrap_or("");
-                                                
-                                                let created_date = path_clone
-                                                    .metadata()
-                                                    .ok()
-                                                    .and_then(|m| m.created().ok());
-                                                
-                                                if let Some(destination) = config.get_destination_folder(
-                                                    &path_clone,
-                                                    &extension_str,
-                                                    file_name,
-                                                    created_date,
-                                                ) {
-                                                    match organize_file_to_destination(&path_clone, &destination, None) {
-                                                        Ok(dest) => {
-                                                            let _ = event_tx_clone2.send(format!(
-                                                                "Moved: {} -> {}",
-                                                                path_clone.display(),
-                                                                dest
-                                                            ));
-                                                        }
-                                                        Err(e) => {
-                                                            let _ = event_tx_clone2.send(format!(
-                                                                "Error organizing {}: {}",
-                                                                path_clone.display(),
-                                                                e
-                                                            ));
-                                                        }
-                                                    }
-                                                } else {
-                                                    let _ = event_tx_clone2.send(format!(
-                                                        "No rule found for {} - file not moved",
-                                                        path_clone.display()
-                                                    ));
-                                                }
-                                            }
-                                            "ask" => {
-                                                if let Err(e) = Self::add_pending_file_internal(
-                                                    &path_clone,
-                                                    &pending_files_clone2,
-                                                    &event_tx_clone2,
-                                                ) {
-                                                    let _ = event_tx_clone2.send(format!(
-                                                        "Error queueing {}: {}",
-                                                        path_clone.display(),
-                                                        e
-                                                    ));
-                                                }
-                                            }
-                                            "both" => {
-                                                let extension_str = path_clone
-                                                    .extension()
-                                                    .and_then(|ext| ext.to_str())
-                                                    .map(|e| e.to_lowercase())
-                                                    .unwrap_or_else(|| "other".to_string());
-                                                
-                                                let file_name = path_clone
-                                                    .file_name()
-                                                    .and_then(|n| n.to_str())
-                                                    .unwrap_or("");
-                                                
-                                                let created_date = path_clone
-                                                    .metadata()
-                                                    .ok()
-                                                    .and_then(|m| m.created().ok());
-                                                
-                                                if let Some(destination) = config.get_destination_folder(
-                                                    &path_clone,
-                                                    &extension_str,
-                                                    file_name,
-                                                    created_date,
-                                                ) {
-                                                    match organize_file_to_destination(&path_clone, &destination, None) {
-                                                        Ok(dest) => {
-                                                            let _ = event_tx_clone2.send(format!(
-                                                                "Moved: {} -> {}",
-                                                                path_clone.display(),
-                                                                dest
-                                                            ));
-                                                        }
-                                                        Err(e) => {
-                                                            let _ = event_tx_clone2.send(format!(
-                                                                "Error organizing {}: {}",
-                                                                path_clone.display(),
-                                                                e
-                                                            ));
-                                                        }
-                                                    }
-                                                } else {
-                                                    if let Err(e) = Self::add_pending_file_internal(
-                                                        &path_clone,
-                                                        &pending_files_clone2,
-                                                        &event_tx_clone2,
-                                                    ) {
-                                                        let _ = event_tx_clone2.send(format!(
-                                                            "Error queueing {}: {}",
-                                                            path_clone.display(),
-                                                            e
-                                                        ));
-                                                    }
-                                                }
-                                            }
-                                            _ => {
-                                                let _ = event_tx_clone2.send(format!(
-                                                    "Unknown organization mode: {}",
-                                                    mode
-                                                ));
-                                            }
-                                        }
-                                    }
-                                });
-                            }
-                        }
+                    for signal in path_signals(event) {
+                        let _ = signal_tx_clone.send(signal);
                     }
+                }
                 Err(e) => {
-                    let _ = event_tx_clone.send(format!("Watch error: {}", e));
+                    let _ = event_tx_clone.send(WatcherEvent::watch_error(e.to_string()));
                 }
             }
         })
@@ -194,113 +98,375 @@ impl FileWatcher {
             config,
             event_tx,
             pending_files,
-            watched_path: Arc::new(Mutex::new(None)),
+            settling_files,
+            in_flight,
+            skips,
+            signal_tx,
+            watched_paths: Arc::new(Mutex::new(Vec::new())),
+            sweep_running: Arc::new(AtomicBool::new(false)),
+            sweep_cancelled: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Runs every file already in `folder` (or in every watched folder) through the
+    /// same pipeline as new files, on a background thread. Progress is reported
+    /// through the event channel and the sweep stops early after `cancel_sweep`.
+    pub fn sweep(&self, folder: Option<&Path>) -> Result<(), String> {
+        let folders = match folder {
+            Some(folder) => vec![normalize_folder(folder)],
+            None => self.watched_folders(),
+        };
+        if folders.is_empty() {
+            return Err("No folder is being watched".to_string());
+        }
+        if self.sweep_running.swap(true, Ordering::SeqCst) {
+            return Err("A sweep is already running".to_string());
+        }
+        self.sweep_cancelled.store(false, Ordering::SeqCst);
+
+        let config = self.config.clone();
+        let pending_files = self.pending_files.clone();
+        let settling_files = self.settling_files.clone();
+        let in_flight = self.in_flight.clone();
+        let skips = self.skips.clone();
+        let signal_tx = self.signal_tx.clone();
+        let event_tx = self.event_tx.clone();
+        let running = self.sweep_running.clone();
+        let cancelled = self.sweep_cancelled.clone();
+
+        std::thread::spawn(move || {
+            // Even after a panic, so later sweeps aren't refused
+            let _running = ClearOnDrop(running);
+            skips.prune();
+            for folder in folders {
+                if cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                let snapshot = config.lock().unwrap().clone();
+                let files = match Self::sweep_candidates(&folder, &snapshot) {
+                    Ok(files) => files,
+                    Err(e) => {
+                        let _ = event_tx.send(WatcherEvent::error(Some(folder.clone()), e));
+                        continue;
+                    }
+                };
+                let total = files.len();
+                let _ = event_tx.send(WatcherEvent::sweep_started(&folder, total));
+
+                let mut processed = 0;
+                for path in files {
+                    if cancelled.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    // The file may have been handled by the watcher since the listing
+                    let settling = settling_files
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .any(|f| Path::new(&f.path) == path);
+                    if path.is_file() && !settling {
+                        let file_config = config.lock().unwrap().for_file(&path);
+                        let quiet_period = SettleOptions::from_config(&file_config).quiet_period;
+                        if !settle::looks_settled(&path, quiet_period) {
+                            // Still being written, so wait for it like a new file
+                            let _ = signal_tx.send(PathSignal::Created(path.clone()));
+                        } else if in_flight.lock().unwrap().insert(path.clone()) {
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                                Self::process_path(&path, &file_config, &pending_files, &skips, &event_tx);
+                            }));
+                            in_flight.lock().unwrap().remove(&path);
+                            if let Err(panic) = result {
+                                let _ = event_tx.send(WatcherEvent::error(
+                                    Some(path.clone()),
+                                    format!("Failed to organize file: {}", panic_message(panic.as_ref())),
+                                ));
+                            }
+                        }
+                    }
+                    processed += 1;
+                    let _ = event_tx.send(WatcherEvent::sweep_progress(&path, processed, total));
+                }
+
+                let _ = event_tx.send(WatcherEvent::sweep_finished(
+                    &folder,
+                    processed,
+                    total,
+                    cancelled.load(Ordering::SeqCst),
+                ));
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_sweep(&self) {
+        self.sweep_cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_sweeping(&self) -> bool {
+        self.sweep_running.load(Ordering::SeqCst)
+    }
+
+    /// Files in `folder`, and in the subfolders its watch entry includes, that
+    /// aren't on the ignore list. `folder` may also be below a watched folder, in
+    /// which case the entry containing it decides how deep to go.
+    pub fn sweep_candidates(folder: &Path, config: &Config) -> Result<Vec<PathBuf>, String> {
+        let entry = config
+            .watch_entry(folder)
+            .or_else(|| config.watch_entry_containing(folder));
+        let skip = entry
+            .map(|entry| config.destinations_inside(entry))
+            .unwrap_or_default();
+        let mut files = Vec::new();
+        let mut dirs = vec![folder.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if dir == folder => return Err(format!("Failed to read watched folder: {}", e)),
+                // A subfolder that can't be read shouldn't stop the rest of the sweep
+                Err(_) => continue,
+            };
+            for dir_entry in entries.flatten() {
+                let path = dir_entry.path();
+                let is_dir = dir_entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                if is_dir {
+                    if entry.is_some_and(|entry| entry.includes_dir(&path, &skip)) {
+                        dirs.push(path);
+                    }
+                } else if path.is_file() && !config.is_ignored(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// The settings for a file's watched folder, or `None` when no enabled entry
+    /// covers the file (outside its depth, excluded, or in one of its destinations).
+    fn config_for_file(config: &Arc<Mutex<Config>>, path: &Path) -> Option<Config> {
+        let config = config.lock().unwrap();
+        let entry = config.watch_entry_for_file(path)?;
+        Some(config.for_folder(Path::new(&entry.path)))
+    }
+
+    /// Applies the configured organization mode to one settled file.
+    fn process_path(
+        path: &Path,
+        config: &Config,
+        pending_files: &Arc<PendingQueue>,
+        skips: &SkipMemory,
+        event_tx: &broadcast::Sender<WatcherEvent>,
+    ) {
+        let mode = config.organization_mode.as_str();
+        match mode {
+            "auto" | "both" => {}
+            "ask" => {
+                Self::queue_path(path, None, config, pending_files, event_tx);
+                return;
+            }
+            _ => {
+                let _ = event_tx.send(WatcherEvent::error(
+                    Some(path.to_path_buf()),
+                    format!("Unknown organization mode: {}", mode),
+                ));
+                return;
+            }
+        }
+
+        let decision = classify_and_route(path, config);
+        match (&decision.destination, &decision.matched_rule) {
+            // Already where its rule puts it; moving or renaming it again would
+            // trigger another event for the same file
+            (Some(destination), _) if path.parent() == Some(Path::new(destination)) => {
+                let _ = event_tx.send(WatcherEvent::skipped(path));
+            }
+            (Some(destination), _) => {
+                let progress = |copied, total| {
+                    let _ = event_tx.send(WatcherEvent::transfer_progress(path, copied, total));
+                };
+                let options = OrganizeOptions::from_decision(&decision);
+                match organize_file_to_destination(path, destination, &options, Some(&progress), Some(skips)) {
+                    Ok(organized) if organized.is_deferred() => {
+                        let existing = organized.conflict.map(|c| c.existing).unwrap_or_default();
+                        let reason = format!("A different file already exists at {}", existing);
+                        Self::queue_path(path, Some(reason), config, pending_files, event_tx);
+                    }
+                    Ok(organized) => {
+                        let _ = event_tx.send(WatcherEvent::organized(path, &organized, decision.matched_rule.clone()));
+                    }
+                    Err(e) => {
+                        let _ = event_tx.send(WatcherEvent::error(Some(path.to_path_buf()), e));
+                    }
+                }
+            }
+            // A rule matched but its destination couldn't be resolved
+            (None, Some(_)) => {
+                let _ = event_tx.send(WatcherEvent::error(Some(path.to_path_buf()), decision.reason.clone()));
+            }
+            (None, None) if mode == "both" => Self::queue_path(path, None, config, pending_files, event_tx),
+            (None, None) => {
+                let _ = event_tx.send(WatcherEvent::rule_miss(path));
+            }
+        }
+    }
+
+    fn queue_path(
+        path: &Path,
+        reason: Option<String>,
+        config: &Config,
+        pending_files: &Arc<PendingQueue>,
+        event_tx: &broadcast::Sender<WatcherEvent>,
+    ) {
+        if let Err(e) = Self::add_pending_file_internal(path, reason, config, pending_files, event_tx) {
+            let _ = event_tx.send(WatcherEvent::error(
+                Some(path.to_path_buf()),
+                format!("Failed to queue file: {}", e),
+            ));
+        }
+    }
+
     fn add_pending_file_internal(
         path: &std::path::Path,
-        pending_files: &Arc<Mutex<Vec<PendingFile>>>,
-        event_tx: &broadcast::Sender<String>,
+        reason: Option<String>,
+        config: &Config,
+        pending_files: &Arc<PendingQueue>,
+        event_tx: &broadcast::Sender<WatcherEvent>,
     ) -> Result<(), String> {
-        let metadata = path.metadata()
-            .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-        
-        let file_name = path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string();
-        
-        let extension = path.extension()
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("")
-            .to_string();
-        
-        let size = metadata.len();
-        
-        let detected_at = SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let pending_file = PendingFile {
-            path: path.to_string_lossy().to_string(),
-            name: file_name.clone(),
-            extension,
-            size,
-            detected_at: detected_at.to_string(),
-        };
+        // A file that is already queued keeps its place and original detection time.
+        // Checked first since describing a file reads the history and hashes it.
+        if pending_files.contains(path) {
+            return Ok(());
+        }
+        let pending_file = pending::pending_file(path, reason.clone(), config)?;
+        let (file_name, size) = (pending_file.name.clone(), pending_file.size);
         
-        let mut pending = pending_files.lock().unwrap();
-        // Check if file is already in the pending list to avoid duplicates
-        let file_path_str = pending_file.path.clone();
-        if !pending.iter().any(|f| f.path == file_path_str) {
-            pending.push(pending_file.clone());
-        } else {
-            // File already exists, don't add duplicate
+        // Queued by another worker in the meantime
+        if !pending_files.push(pending_file) {
             return Ok(());
         }
         
-        let notification_data = format!(
-            "file_queued:{}|{}|{}",
-            path.display(),
-            file_name,
-            size
-        );
-        let _ = event_tx.send(notification_data);
+        let _ = event_tx.send(WatcherEvent::queued(path, file_name, size, reason));
         
         Ok(())
     }
 
-    pub fn watch(&mut self, path: &Path) -> Result<(), String> {
-        let path_buf = path.to_path_buf();
-        
-        // Unwatch old path if different
-        let mut watched_path_guard = self.watched_path.lock().unwrap();
-        if let Some(old_path) = watched_path_guard.as_ref() {
-            if old_path != path {
-                let _ = self.watcher.unwatch(old_path);
-            }
+    /// Adds `path` to the watched folders; folders already being watched stay watched.
+    /// Depth limits and exclusions are applied to events, since the OS watch
+    /// itself covers the whole tree.
+    pub fn watch(&mut self, path: &Path, recursive: bool) -> Result<(), String> {
+        // The form config entries use, so event paths line up with them
+        let path = &normalize_folder(path);
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        match self.watched_mode(path) {
+            Some(current) if current == mode => return Ok(()),
+            Some(_) => self.unwatch(path)?,
+            None => {}
         }
-        
+
         self.watcher
-            .watch(path, RecursiveMode::NonRecursive)
+            .watch(path, mode)
             .map_err(|e| format!("Failed to watch path: {}", e))?;
-        
-        *watched_path_guard = Some(path_buf);
+
+        self.watched_paths.lock().unwrap().push((path.to_path_buf(), mode));
         Ok(())
     }
 
-    pub fn update_config(&self, config: Config) -> Result<(), String> {
-        *self.config.lock().unwrap() = config;
-        Ok(())
+    fn watched_mode(&self, path: &Path) -> Option<RecursiveMode> {
+        self.watched_paths
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, mode)| *mode)
     }
-    
-    pub fn unwatch_current(&mut self) -> Result<(), String> {
-        let mut watched_path_guard = self.watched_path.lock().unwrap();
-        if let Some(path) = watched_path_guard.take() {
+
+    pub fn unwatch(&mut self, path: &Path) -> Result<(), String> {
+        let path = &normalize_folder(path);
+        let mut watched_paths = self.watched_paths.lock().unwrap();
+        if let Some(index) = watched_paths.iter().position(|(p, _)| p == path) {
+            watched_paths.remove(index);
             self.watcher
-                .unwatch(&path)
+                .unwatch(path)
                 .map_err(|e| format!("Failed to unwatch path: {}", e))?;
         }
         Ok(())
     }
 
-    pub fn get_event_receiver(&self) -> broadcast::Receiver<String> {
+    pub fn unwatch_all(&mut self) -> Result<(), String> {
+        self.cancel_sweep();
+        for path in self.watched_folders() {
+            self.unwatch(&path)?;
+        }
+        Ok(())
+    }
+
+    pub fn watched_folders(&self) -> Vec<PathBuf> {
+        self.watched_paths
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Watches exactly the enabled entries of the current config. Entries that
+    /// fail to watch are reported together; the others are still applied.
+    pub fn sync_with_config(&mut self) -> Result<(), String> {
+        let entries = self.config.lock().unwrap().watched_folders.clone();
+        for path in self.watched_folders() {
+            if !entries.iter().any(|entry| entry.enabled && entry.is_for(&path)) {
+                self.unwatch(&path)?;
+            }
+        }
+
+        let mut failures = Vec::new();
+        for entry in entries.iter().filter(|entry| entry.enabled) {
+            if let Err(e) = self.watch(Path::new(&entry.path), entry.recursive) {
+                failures.push(format!("{}: {}", entry.path, e));
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.join("; "))
+        }
+    }
+
+    pub fn update_config(&self, config: Config) -> Result<(), String> {
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+
+    pub fn get_event_receiver(&self) -> broadcast::Receiver<WatcherEvent> {
         self.event_tx.subscribe()
     }
 
     pub fn add_pending_file(&self, file_path: std::path::PathBuf) -> Result<(), String> {
-        Self::add_pending_file_internal(&file_path, &self.pending_files, &self.event_tx)
+        let config = self.file_config(&file_path);
+        Self::add_pending_file_internal(&file_path, None, &config, &self.pending_files, &self.event_tx)
+    }
+
+    /// The settings for a file's watched folder, or the top-level ones for files
+    /// outside every watched folder.
+    fn file_config(&self, path: &Path) -> Config {
+        Self::config_for_file(&self.config, path).unwrap_or_else(|| self.config.lock().unwrap().clone())
     }
 
     pub fn get_pending_files(&self) -> Vec<PendingFile> {
-        self.pending_files.lock().unwrap().clone()
+        self.pending_files.list()
+    }
+
+    pub fn get_settling_files(&self) -> Vec<SettlingFile> {
+        self.settling_files.lock().unwrap().clone()
     }
 
     pub fn remove_pending_file(&self, path: &str) -> Result<(), String> {
-        let mut pending = self.pending_files.lock().unwrap();
-        pending.retain(|f| f.path != path);
+        self.pending_files.remove(Path::new(path));
         Ok(())
     }
 
@@ -313,23 +479,319 @@ impl FileWatcher {
         }
 
         if let Some(dest) = destination {
-            match organize_file_to_destination(&path, &dest, new_name.as_deref()) {
-                Ok(_) => {
-                    self.remove_pending_file(file_path)?;
-                    let _ = self.event_tx.send(format!(
-                        "Moved: {} -> {}",
-                        path.display(),
-                        dest
-                    ));
-                    Ok(())
-                }
-                Err(e) => Err(format!("Failed to move file: {}", e))
-            }
+            let options = OrganizeOptions {
+                new_name,
+                ..OrganizeOptions::default()
+            };
+            self.organize_pending_file(&path, &dest, &options)
         } else {
             self.remove_pending_file(file_path)?;
-            let _ = self.event_tx.send(format!("Skipped: {}", path.display()));
+            let mut entry = HistoryEntry::new(HistoryAction::Skipped, file_path);
+            entry.size = path.metadata().ok().map(|m| m.len());
+            history::record(entry);
+            let _ = self.event_tx.send(WatcherEvent::skipped(&path));
             Ok(())
         }
     }
+
+    /// Sends a pending file to one of its suggested destinations. A rule's
+    /// suggestion is carried out with the rule's action and policies, as long as
+    /// the rule still routes the file there; anything else is a plain move.
+    pub fn accept_suggestion(&self, file_path: &str, index: usize) -> Result<(), String> {
+        let path = PathBuf::from(file_path);
+        let suggestion = self
+            .pending_files
+            .list()
+            .into_iter()
+            .find(|f| f.path == file_path)
+            .and_then(|f| f.suggestions.get(index).cloned())
+            .ok_or_else(|| "Suggestion not found".to_string())?;
+
+        if !path.exists() {
+            self.remove_pending_file(file_path)?;
+            return Err("File no longer exists".to_string());
+        }
+
+        let decision = classify_and_route(&path, &self.file_config(&path));
+        let options = match &suggestion.rule {
+            Some(rule)
+                if decision.matched_rule.as_ref() == Some(rule)
+                    && decision.destination.as_ref() == Some(&suggestion.destination) =>
+            {
+                let mut options = OrganizeOptions::from_decision(&decision);
+                // The user has just decided, so asking again would queue it right back
+                if options.on_conflict == ConflictPolicy::Ask {
+                    options.on_conflict = ConflictPolicy::KeepBoth;
+                }
+                options
+            }
+            _ => OrganizeOptions {
+                new_name: suggestion.new_name,
+                ..OrganizeOptions::default()
+            },
+        };
+        self.organize_pending_file(&path, &suggestion.destination, &options)
+    }
+
+    fn organize_pending_file(&self, path: &Path, destination: &str, options: &OrganizeOptions) -> Result<(), String> {
+        let progress = |copied, total| {
+            let _ = self.event_tx.send(WatcherEvent::transfer_progress(path, copied, total));
+        };
+        let organized = organize_file_to_destination(path, destination, options, Some(&progress), None)?;
+        self.pending_files.remove(path);
+        let _ = self.event_tx.send(WatcherEvent::organized(path, &organized, options.rule.clone()));
+        Ok(())
+    }
 }
 
+fn path_signals(event: Event) -> Vec<PathSignal> {
+    let mut paths = event.paths;
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            paths.into_iter().map(PathSignal::Created).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+            let to = paths.pop().unwrap();
+            let from = paths.pop().unwrap();
+            vec![PathSignal::Renamed { from, to }]
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            paths.into_iter().map(PathSignal::Removed).collect()
+        }
+        // Platforms that don't say which side of a rename a path is on
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .into_iter()
+            .map(|path| {
+                if path.exists() {
+                    PathSignal::Created(path)
+                } else {
+                    PathSignal::Removed(path)
+                }
+            })
+            .collect(),
+        EventKind::Modify(ModifyKind::Data(_)) | EventKind::Modify(ModifyKind::Any) => {
+            paths.into_iter().map(PathSignal::Written).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Merges the events for each path into one job: a path is followed from its
+/// first event until it settles, then organized once on the worker pool.
+struct Coalescer {
+    config: Arc<Mutex<Config>>,
+    pending_files: Arc<PendingQueue>,
+    settling_files: Arc<Mutex<Vec<SettlingFile>>>,
+    /// Paths currently being organized by a worker or a sweep.
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+    skips: Arc<SkipMemory>,
+    event_tx: broadcast::Sender<WatcherEvent>,
+    pool: WorkerPool,
+    tracked: HashMap<PathBuf, SettleTracker>,
+    /// Files given up on because they kept changing; a later write means they are
+    /// being worked on again and are followed anew.
+    timed_out: HashSet<PathBuf>,
+    /// Results of open-file checks run on the pool.
+    open_checks: (mpsc::Sender<OpenCheck>, mpsc::Receiver<OpenCheck>),
+}
+
+/// A path and whether another process has it open.
+type OpenCheck = (PathBuf, bool);
+
+impl Coalescer {
+    /// Runs until the watcher is dropped.
+    fn run(mut self, signals: mpsc::Receiver<PathSignal>) {
+        let mut last_poll = Instant::now();
+        loop {
+            match signals.recv_timeout(SETTLE_TICK) {
+                Ok(signal) => self.handle(signal),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            while let Ok((path, open)) = self.open_checks.1.try_recv() {
+                self.open_checked(path, open);
+            }
+            if last_poll.elapsed() >= SETTLE_TICK {
+                self.poll();
+                last_poll = Instant::now();
+            }
+        }
+    }
+
+    fn handle(&mut self, signal: PathSignal) {
+        match signal {
+            PathSignal::Created(path) => self.track(path),
+            PathSignal::Written(path) => {
+                if let Some(tracker) = self.tracked.get_mut(&path) {
+                    tracker.touch();
+                } else if self.timed_out.contains(&path) {
+                    self.track(path);
+                }
+            }
+            PathSignal::Removed(path) => {
+                self.timed_out.remove(&path);
+                if self.forget(&path) {
+                    let _ = self.event_tx.send(WatcherEvent::vanished(&path));
+                }
+                self.pending_files.remove(&path);
+                self.skips.forget(&path);
+            }
+            PathSignal::Renamed { from, to } => {
+                self.timed_out.remove(&from);
+                if self.forget(&from) {
+                    let _ = self.event_tx.send(WatcherEvent::vanished(&from));
+                }
+                self.skips.forget(&from);
+                // A queued file keeps its place in the queue under its new name
+                if !self.pending_files.rename(&from, &to) {
+                    self.track(to);
+                }
+            }
+        }
+    }
+
+    fn track(&mut self, path: PathBuf) {
+        if let Some(tracker) = self.tracked.get_mut(&path) {
+            tracker.touch();
+            return;
+        }
+        self.timed_out.remove(&path);
+        if path.is_dir() {
+            self.track_folder(&path);
+            return;
+        }
+        if !path.is_file() || self.in_flight.lock().unwrap().contains(&path) {
+            return;
+        }
+        {
+            // Skip in-progress downloads, lock files and other temporary files
+            let config = self.config.lock().unwrap();
+            if config.is_ignored(&path) || config.watch_entry_for_file(&path).is_none() {
+                return;
+            }
+        }
+        self.settling_files.lock().unwrap().push(SettlingFile::new(&path));
+        let _ = self.event_tx.send(WatcherEvent::settling(&path));
+        let tracker = SettleTracker::new(&path);
+        self.tracked.insert(path, tracker);
+    }
+
+    /// Follows the files in a folder created or moved into a recursive watch, since
+    /// files that were already inside it don't get events of their own.
+    fn track_folder(&mut self, dir: &Path) {
+        let files = {
+            let config = self.config.lock().unwrap();
+            let included = config
+                .watch_entry_containing(dir)
+                .is_some_and(|entry| entry.enabled && entry.includes_dir(dir, &config.destinations_inside(entry)));
+            if !included {
+                return;
+            }
+            FileWatcher::sweep_candidates(dir, &config).unwrap_or_default()
+        };
+        for file in files {
+            self.track(file);
+        }
+    }
+
+    /// Stops following a path. Returns whether it was settling.
+    fn forget(&mut self, path: &Path) -> bool {
+        if self.tracked.remove(path).is_none() {
+            return false;
+        }
+        self.settling_files
+            .lock()
+            .unwrap()
+            .retain(|f| Path::new(&f.path) != path);
+        true
+    }
+
+    fn poll(&mut self) {
+        if self.tracked.is_empty() {
+            return;
+        }
+        let options = SettleOptions::from_config(&self.config.lock().unwrap());
+        let finished: Vec<(PathBuf, SettleOutcome)> = self
+            .tracked
+            .iter_mut()
+            .filter_map(|(path, tracker)| tracker.poll(path, options).map(|outcome| (path.clone(), outcome)))
+            .collect();
+
+        for (path, outcome) in finished {
+            if outcome == SettleOutcome::Quiet {
+                self.check_open(path);
+                continue;
+            }
+            self.forget(&path);
+            match outcome {
+                SettleOutcome::Quiet => {}
+                SettleOutcome::Vanished => {
+                    let _ = self.event_tx.send(WatcherEvent::vanished(&path));
+                }
+                SettleOutcome::TimedOut => {
+                    self.timed_out.insert(path.clone());
+                    let _ = self.event_tx.send(WatcherEvent::error(
+                        Some(path),
+                        format!(
+                            "File was still changing after {} seconds and is left in place until it is written again",
+                            options.max_wait.as_secs()
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Checks on the pool whether another process still has a quiet file open,
+    /// since that can be slow and every other settling file waits on this thread.
+    fn check_open(&mut self, path: PathBuf) {
+        if let Some(tracker) = self.tracked.get_mut(&path) {
+            tracker.begin_open_check();
+        }
+        let results = self.open_checks.0.clone();
+        self.pool.submit(move || {
+            let open = settle::is_open_elsewhere(&path);
+            let _ = results.send((path, open));
+        });
+    }
+
+    fn open_checked(&mut self, path: PathBuf, open: bool) {
+        match self.tracked.get_mut(&path) {
+            // Removed, renamed or timed out while being checked
+            None => {}
+            Some(tracker) if open => tracker.still_open(),
+            Some(_) => {
+                self.forget(&path);
+                let _ = self.event_tx.send(WatcherEvent::settled(&path));
+                self.dispatch(path);
+            }
+        }
+    }
+
+    fn dispatch(&self, path: PathBuf) {
+        if !self.in_flight.lock().unwrap().insert(path.clone()) {
+            return;
+        }
+        let config = self.config.clone();
+        let pending_files = self.pending_files.clone();
+        let in_flight = self.in_flight.clone();
+        let skips = self.skips.clone();
+        let event_tx = self.event_tx.clone();
+        self.pool.submit(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                // The config may have changed while the file was settling
+                if let Some(config) = FileWatcher::config_for_file(&config, &path) {
+                    FileWatcher::process_path(&path, &config, &pending_files, &skips, &event_tx);
+                }
+            }));
+            // Even after a panic, so the file is picked up again next time
+            in_flight.lock().unwrap().remove(&path);
+            if let Err(panic) = result {
+                let _ = event_tx.send(WatcherEvent::error(
+                    Some(path),
+                    format!("Failed to organize file: {}", panic_message(panic.as_ref())),
+                ));
+            }
+        });
+    }
+}
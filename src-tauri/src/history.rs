@@ -0,0 +1,194 @@
+use crate::config::Config;
+use crate::events;
+use crate::file_organizer::Conflict;
+use crate::routing::RuleMatch;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Serializes appends and id allocation across watcher threads.
+static HISTORY_LOCK: Mutex<u64> = Mutex::new(0);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    Moved,
+    Copied,
+    Symlinked,
+    Hardlinked,
+    Skipped,
+    Deleted,
+    /// A previous move was undone; `reverts` holds its id.
+    Reverted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HistoryOutcome {
+    Success,
+    Failed { message: String },
+}
+
+/// One line of the history log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unique, increasing id: microseconds since the epoch, bumped on collisions.
+    /// Microseconds keep it within JavaScript's safe integer range.
+    pub id: u64,
+    pub timestamp: u64,
+    pub action: HistoryAction,
+    pub source: String,
+    #[serde(default)]
+    pub destination: Option<String>,
+    #[serde(default)]
+    pub rule: Option<RuleMatch>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    pub outcome: HistoryOutcome,
+    #[serde(default)]
+    pub reverts: Option<u64>,
+    /// A file with identical content that was already in the destination.
+    #[serde(default)]
+    pub duplicate_of: Option<String>,
+    /// The file replaced `duplicate_of` in place, so `destination` existed before.
+    #[serde(default)]
+    pub replaced_duplicate: bool,
+    /// Set when a different file already had the target name.
+    #[serde(default)]
+    pub conflict: Option<Conflict>,
+}
+
+impl HistoryEntry {
+    pub fn new(action: HistoryAction, source: impl Into<String>) -> Self {
+        HistoryEntry {
+            id: 0,
+            timestamp: events::now(),
+            action,
+            source: source.into(),
+            destination: None,
+            rule: None,
+            size: None,
+            outcome: HistoryOutcome::Success,
+            reverts: None,
+            duplicate_of: None,
+            replaced_duplicate: false,
+            conflict: None,
+        }
+    }
+}
+
+/// Filters and pagination for `query`. Every filter is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub action: Option<HistoryAction>,
+    /// `true` for successful entries only, `false` for failures only.
+    pub success: Option<bool>,
+    /// Matches the rule's name, or its 1-based number for unnamed rules.
+    pub rule: Option<String>,
+    /// Case-insensitive substring of the source or destination path.
+    pub search: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub offset: usize,
+    /// Defaults to 50 when zero.
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPage {
+    /// Newest first.
+    pub entries: Vec<HistoryEntry>,
+    /// Number of entries matching the filters, before pagination.
+    pub total: usize,
+}
+
+pub fn history_path() -> PathBuf {
+    Config::config_path().with_file_name("history.jsonl")
+}
+
+/// Appends an entry, assigning its id. Failures are logged rather than returned so
+/// that a full disk never stops files from being organized.
+pub fn record(mut entry: HistoryEntry) -> HistoryEntry {
+    let mut last_id = HISTORY_LOCK.lock().unwrap();
+    let micros = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    entry.id = micros.max(*last_id + 1);
+    *last_id = entry.id;
+
+    if let Err(e) = append(&entry) {
+        eprintln!("Failed to record history: {}", e);
+    }
+    entry
+}
+
+fn append(entry: &HistoryEntry) -> Result<(), String> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let mut line = serde_json::to_string(entry).map_err(|e| format!("Failed to serialize history entry: {}", e))?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open history: {}", e))?;
+    file.write_all(line.as_bytes())
+        .map_err(|e| format!("Failed to write history: {}", e))
+}
+
+/// Every recorded entry, oldest first. Lines that fail to parse (e.g. a write cut
+/// short by a crash) are skipped.
+pub fn load_all() -> Vec<HistoryEntry> {
+    let _guard = HISTORY_LOCK.lock().unwrap();
+    let Ok(content) = fs::read_to_string(history_path()) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub fn query(query: &HistoryQuery) -> HistoryPage {
+    let search = query.search.as_ref().map(|s| s.to_lowercase());
+    let matching: Vec<HistoryEntry> = load_all()
+        .into_iter()
+        .rev()
+        .filter(|entry| {
+            query.action.map_or(true, |action| entry.action == action)
+                && query.success.map_or(true, |success| {
+                    (entry.outcome == HistoryOutcome::Success) == success
+                })
+                && query.rule.as_ref().map_or(true, |rule| rule_matches(entry, rule))
+                && search.as_ref().map_or(true, |search| {
+                    entry.source.to_lowercase().contains(search)
+                        || entry
+                            .destination
+                            .as_ref()
+                            .map_or(false, |d| d.to_lowercase().contains(search))
+                })
+                && query.since.map_or(true, |since| entry.timestamp >= since)
+                && query.until.map_or(true, |until| entry.timestamp <= until)
+        })
+        .collect();
+
+    let limit = if query.limit == 0 { 50 } else { query.limit };
+    HistoryPage {
+        total: matching.len(),
+        entries: matching.into_iter().skip(query.offset).take(limit).collect(),
+    }
+}
+
+pub fn rule_matches(entry: &HistoryEntry, rule: &str) -> bool {
+    match &entry.rule {
+        Some(RuleMatch { name: Some(name), .. }) => name == rule,
+        Some(RuleMatch { index, name: None }) => (index + 1).to_string() == rule,
+        None => false,
+    }
+}
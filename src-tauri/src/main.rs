@@ -1,13 +1,32 @@
 mod commands;
 mod config;
+mod duplicates;
+mod events;
 mod file_organizer;
+mod file_type;
 mod file_watcher;
+mod history;
+mod patterns;
+mod pending;
+mod preview;
+mod routing;
+mod settle;
+mod suggestions;
+mod templates;
+#[cfg(test)]
+mod test_support;
+mod thumbnails;
+mod transfer;
+mod undo;
+mod worker_pool;
 
 use commands::*;
+use events::{WatcherEvent, WATCHER_EVENT};
 use tauri::tray::TrayIconBuilder;
 use tauri::Manager;
 use tauri::Emitter;
 use tauri::menu::{Menu, MenuItem};
+use tokio::sync::broadcast;
 
 fn main() {
     tauri::Builder::default()
@@ -297,28 +316,26 @@ fn main() {
                 let handle = rt.handle().clone();
                 handle.spawn(async move {
                     loop {
-                        if let Ok(msg) = rx.recv().await {
-                            if msg.starts_with("file_queued:") {
-                                let parts: Vec<&str> = msg.splitn(3, '|').collect();
-                                if parts.len() >= 3 {
-                                    let file_path = parts[0].strip_prefix("file_queued:").unwrap_or("");
-                                    let file_name = parts[1];
-                                    let file_size: u64 = parts[2].parse().unwrap_or(0);
-                                    
-                                    let app_for_modal = app_handle.clone();
-                                    let file_path_clone = file_path.to_string();
-                                    let file_name_clone = file_name.to_string();
-                                    
-                                    // Show the modal window
-                                    if let Err(e) = commands::show_file_organization_modal(
-                                        app_for_modal,
-                                        file_path_clone,
-                                        file_name_clone,
-                                        file_size,
-                                    ) {
-                                        eprintln!("Failed to show file organization modal: {}", e);
-                                    }
-                                }
+                        let event = match rx.recv().await {
+                            Ok(event) => event,
+                            // Dropped events only matter to the UI log; keep forwarding
+                            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        };
+
+                        if let Err(e) = app_handle.emit(WATCHER_EVENT, &event) {
+                            eprintln!("Failed to forward watcher event: {}", e);
+                        }
+
+                        if let WatcherEvent::Queued { path, name, size, .. } = event {
+                            // Show the modal window
+                            if let Err(e) = commands::show_file_organization_modal(
+                                app_handle.clone(),
+                                path.to_string_lossy().to_string(),
+                                name,
+                                size,
+                            ) {
+                                eprintln!("Failed to show file organization modal: {}", e);
                             }
                         }
                     }
@@ -327,6 +344,16 @@ fn main() {
                 rt.block_on(std::future::pending::<()>());
             });
 
+            // Files still waiting from the last session get the modal right away
+            if commands::get_pending_files().is_ok_and(|files| !files.is_empty()) {
+                let app_handle = app.handle().clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = commands::show_file_organization_modal(app_handle, String::new(), String::new(), 0) {
+                        eprintln!("Failed to show file organization modal: {}", e);
+                    }
+                });
+            }
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -334,12 +361,23 @@ fn main() {
             save_config,
             start_watching,
             stop_watching,
+            remove_watched_folder,
+            get_watched_folders,
+            sweep_watched_folder,
+            cancel_sweep,
             get_organization_mode,
             set_organization_mode,
             get_pending_files,
+            get_settling_files,
             process_pending_file,
+            accept_suggestion,
             delete_pending_file,
             move_file_manual,
+            get_routing_decision,
+            get_history,
+            preview_rules,
+            undo_last_move,
+            revert_moves,
             show_file_notification,
             process_file_from_notification,
             open_settings_window,
@@ -0,0 +1,142 @@
+use regex::Regex;
+
+/// Translates a glob pattern into an anchored regular expression.
+///
+/// Supports `*` (anything except `/`), `**` (anything, including `/`), `?` (one
+/// character except `/`), `[abc]` / `[a-z]` character classes and `[!abc]` negation.
+/// Every other character matches literally.
+pub fn glob_to_regex(pattern: &str) -> Result<String, String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut regex = String::from("^");
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                if chars.get(i) == Some(&'/') {
+                    // `**/` also matches zero directories
+                    regex.push_str("(?:.*/)?");
+                    i += 1;
+                } else {
+                    regex.push_str(".*");
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let mut start = i + 1;
+                if chars.get(start) == Some(&'!') {
+                    start += 1;
+                }
+                // A `]` right after `[` or `[!` is a literal member of the class
+                if chars.get(start) == Some(&']') {
+                    start += 1;
+                }
+                let close = (start..chars.len())
+                    .find(|&j| chars[j] == ']')
+                    .ok_or_else(|| format!("unclosed '[' in pattern '{}'", pattern))?;
+                let mut class: String = chars[i + 1..close].iter().collect();
+                if let Some(rest) = class.strip_prefix('!') {
+                    class = format!("^{}", rest);
+                }
+                regex.push('[');
+                regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                regex.push(']');
+                i = close;
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+
+    regex.push('$');
+    Ok(regex)
+}
+
+pub fn compile_glob(pattern: &str) -> Result<Regex, String> {
+    let regex = glob_to_regex(pattern)?;
+    Regex::new(&regex).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))
+}
+
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    compile_glob(pattern)
+        .map(|re| re.is_match(text))
+        .unwrap_or(false)
+}
+
+/// Makes a value captured from a file name safe to use as a single path component.
+pub fn sanitize_path_component(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '-' } else { c })
+        .collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "_".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_stays_within_one_folder() {
+        assert!(glob_matches("*.pdf", "report.pdf"));
+        assert!(glob_matches("*.pdf", ".pdf"));
+        assert!(!glob_matches("*.pdf", "scans/report.pdf"));
+        assert!(!glob_matches("*.pdf", "report.pdf.bak"));
+    }
+
+    #[test]
+    fn question_mark_is_one_character() {
+        assert!(glob_matches("IMG_????.jpg", "IMG_0042.jpg"));
+        assert!(!glob_matches("IMG_????.jpg", "IMG_042.jpg"));
+        assert!(!glob_matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_star_crosses_folders() {
+        assert!(glob_matches("scans/**/*.pdf", "scans/2024/march/a.pdf"));
+        // `**/` also matches no folder at all
+        assert!(glob_matches("scans/**/*.pdf", "scans/a.pdf"));
+        assert!(glob_matches("**/*.pdf", "a.pdf"));
+        assert!(glob_matches("scans/**", "scans/2024/a.pdf"));
+        assert!(!glob_matches("scans/**/*.pdf", "other/a.pdf"));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(glob_matches("[abc].txt", "b.txt"));
+        assert!(!glob_matches("[abc].txt", "d.txt"));
+        assert!(glob_matches("[a-c]x", "bx"));
+        assert!(glob_matches("[!a]x", "bx"));
+        assert!(!glob_matches("[!a]x", "ax"));
+        // A `]` right after the opening bracket is a member
+        assert!(glob_matches("[]]x", "]x"));
+        assert!(glob_matches("[!]]x", "ax"));
+        assert!(!glob_matches("[!]]x", "]x"));
+    }
+
+    #[test]
+    fn unterminated_class_is_an_error() {
+        assert!(glob_to_regex("[abc").is_err());
+        assert!(glob_to_regex("[]").is_err());
+        assert!(compile_glob("report[").is_err());
+        assert!(!glob_matches("[abc", "[abc"));
+    }
+
+    #[test]
+    fn regex_metacharacters_match_literally() {
+        assert!(glob_matches("a.b", "a.b"));
+        assert!(!glob_matches("a.b", "axb"));
+        assert!(glob_matches("c++ (draft)", "c++ (draft)"));
+        assert!(!glob_matches("c++", "ccc"));
+        assert!(glob_matches("$1^{2}|x", "$1^{2}|x"));
+        assert!(glob_matches("a\\b", "a\\b"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::{Config, PendingFile};
+use crate::events;
+use crate::file_type;
+use crate::suggestions::suggest;
+use crate::thumbnails::{create_thumbnail, discard_thumbnail};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Files waiting for the user to choose a destination. Every change is saved next
+/// to config.json, so the queue survives restarts and crashes.
+pub struct PendingQueue {
+    files: Mutex<Vec<PendingFile>>,
+    path: PathBuf,
+}
+
+pub fn queue_path() -> PathBuf {
+    Config::config_path().with_file_name("pending.json")
+}
+
+/// Describes a file for the queue, with its type, a thumbnail and the
+/// destinations `config` and past choices suggest for it.
+pub fn pending_file(path: &Path, reason: Option<String>, config: &Config) -> Result<PendingFile, String> {
+    let metadata = path
+        .metadata()
+        .map_err(|e| format!("Failed to get file metadata: {}", e))?;
+    let (matched_rule, suggestions) = suggest(path, config);
+    Ok(PendingFile {
+        path: path.to_string_lossy().to_string(),
+        name: path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        extension: path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_string(),
+        size: metadata.len(),
+        detected_at: events::now().to_string(),
+        reason,
+        mime_type: file_type::detect(path).map(|t| t.mime.to_string()),
+        matched_rule,
+        suggestions,
+        thumbnail: create_thumbnail(path).map(|t| t.to_string_lossy().to_string()),
+    })
+}
+
+impl PendingQueue {
+    /// Loads the saved queue, dropping files that were moved or deleted while the
+    /// app wasn't running. Entries keep their original `detected_at`.
+    pub fn load() -> Self {
+        Self::load_from(queue_path())
+    }
+
+    /// Like `load`, from a queue saved at `path`. A queue that can't be read is
+    /// renamed to `pending.json.bad` so that starting empty doesn't overwrite it.
+    pub fn load_from(path: PathBuf) -> Self {
+        let saved: Vec<PendingFile> = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Failed to parse pending files, keeping them in pending.json.bad: {}", e);
+                if let Err(e) = fs::rename(&path, path.with_extension("json.bad")) {
+                    eprintln!("Failed to set aside pending files: {}", e);
+                }
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let count = saved.len();
+        let (files, gone): (Vec<PendingFile>, Vec<PendingFile>) = saved
+            .into_iter()
+            .partition(|file| Path::new(&file.path).is_file());
+        discard_thumbnails(&gone, &files);
+
+        let queue = PendingQueue {
+            files: Mutex::new(Vec::new()),
+            path,
+        };
+        if files.len() != count {
+            queue.save(&files);
+        }
+        *queue.files.lock().unwrap() = files;
+        queue
+    }
+
+    pub fn list(&self) -> Vec<PendingFile> {
+        self.files.lock().unwrap().clone()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().iter().any(|f| Path::new(&f.path) == path)
+    }
+
+    /// Adds a file unless it is already queued. Returns whether it was added.
+    pub fn push(&self, file: PendingFile) -> bool {
+        let mut files = self.files.lock().unwrap();
+        if files.iter().any(|f| f.path == file.path) {
+            return false;
+        }
+        files.push(file);
+        self.save(&files);
+        true
+    }
+
+    pub fn remove(&self, path: &Path) {
+        let mut files = self.files.lock().unwrap();
+        let (removed, kept): (Vec<PendingFile>, Vec<PendingFile>) =
+            files.drain(..).partition(|f| Path::new(&f.path) == path);
+        *files = kept;
+        if !removed.is_empty() {
+            discard_thumbnails(&removed, &files);
+            self.save(&files);
+        }
+    }
+
+    /// Follows a queued file that was renamed. Returns whether it was queued.
+    pub fn rename(&self, from: &Path, to: &Path) -> bool {
+        let mut files = self.files.lock().unwrap();
+        let mut renamed = false;
+        for file in files.iter_mut().filter(|f| Path::new(&f.path) == from) {
+            file.path = to.to_string_lossy().to_string();
+            file.name = to
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            renamed = true;
+        }
+        if renamed {
+            self.save(&files);
+        }
+        renamed
+    }
+
+    /// Failures are logged rather than returned, like history writes.
+    fn save(&self, files: &[PendingFile]) {
+        if let Err(e) = write_queue(&self.path, files) {
+            eprintln!("Failed to save pending files: {}", e);
+        }
+    }
+}
+
+/// Deletes the thumbnails of `removed` files that no `remaining` file shares.
+fn discard_thumbnails(removed: &[PendingFile], remaining: &[PendingFile]) {
+    let in_use: Vec<&str> = remaining.iter().filter_map(|f| f.thumbnail.as_deref()).collect();
+    for thumbnail in removed.iter().filter_map(|f| f.thumbnail.as_deref()) {
+        discard_thumbnail(thumbnail, &in_use);
+    }
+}
+
+/// Writes through a temporary file so a crash never leaves a truncated queue.
+fn write_queue(path: &Path, files: &[PendingFile]) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let json =
+        serde_json::to_string_pretty(files).map_err(|e| format!("Failed to serialize pending files: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json).map_err(|e| format!("Failed to write pending files: {}", e))?;
+    fs::rename(&temp, path).map_err(|e| format!("Failed to write pending files: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+
+    fn queued(path: &Path, detected_at: &str) -> PendingFile {
+        PendingFile {
+            path: path.to_string_lossy().to_string(),
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            extension: "txt".to_string(),
+            size: 1,
+            detected_at: detected_at.to_string(),
+            reason: None,
+            mime_type: None,
+            matched_rule: None,
+            suggestions: Vec::new(),
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn load_drops_missing_files_and_keeps_detection_time() {
+        let dir = scratch_dir("pending-load");
+        let kept = dir.join("kept.txt");
+        let removed = dir.join("removed.txt");
+        fs::write(&kept, "k").unwrap();
+        fs::write(&removed, "r").unwrap();
+
+        let queue_file = dir.join("pending.json");
+        let queue = PendingQueue::load_from(queue_file.clone());
+        assert!(queue.push(queued(&kept, "1000")));
+        assert!(queue.push(queued(&removed, "2000")));
+        assert!(!queue.push(queued(&kept, "3000")));
+        drop(queue);
+
+        fs::remove_file(&removed).unwrap();
+        let reloaded = PendingQueue::load_from(queue_file.clone());
+        let files = reloaded.list();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, kept.to_string_lossy());
+        assert_eq!(files[0].detected_at, "1000");
+        // The dropped entry is gone from disk too
+        assert_eq!(PendingQueue::load_from(queue_file).list().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_sets_aside_an_unreadable_queue() {
+        let dir = scratch_dir("pending-corrupt");
+        let queue_file = dir.join("pending.json");
+        fs::write(&queue_file, "[{\"path\": ").unwrap();
+
+        let queue = PendingQueue::load_from(queue_file.clone());
+        assert!(queue.list().is_empty());
+        assert_eq!(fs::read_to_string(dir.join("pending.json.bad")).unwrap(), "[{\"path\": ");
+
+        let file = dir.join("new.txt");
+        fs::write(&file, "n").unwrap();
+        queue.push(queued(&file, "1000"));
+        assert!(dir.join("pending.json.bad").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
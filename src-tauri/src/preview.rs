@@ -0,0 +1,87 @@
+use crate::config::{normalize_folder, Config};
+use crate::file_organizer::{plan_placements, OrganizeOptions, PlannedPlacement};
+use crate::file_watcher::FileWatcher;
+use crate::routing::{classify_and_route, RoutingDecision, RuleMatch};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewEntry {
+    pub path: String,
+    pub decision: RoutingDecision,
+    /// Full path the file would be placed at in its destination, after duplicate
+    /// and conflict handling; `None` if it would be left where it is.
+    pub final_path: Option<String>,
+    /// What would happen in each destination, including `MultiCopy` extras.
+    pub placements: Vec<PlannedPlacement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewReport {
+    pub files: Vec<PreviewEntry>,
+    /// Files no rule would move.
+    pub unmatched_files: Vec<String>,
+    /// Rules that wouldn't be the first match for any file in the folder.
+    pub unused_rules: Vec<RuleMatch>,
+}
+
+/// Shows what `config` would do to every file a sweep of `folder` would pick up,
+/// without moving anything.
+pub fn preview_rules(config: &Config, folder: &Path) -> Result<PreviewReport, String> {
+    config.validate()?;
+
+    // The folder's own mode and rules, if it is watched with overrides
+    let folder = normalize_folder(folder);
+    let config = &config.for_folder(&folder);
+    let paths = FileWatcher::sweep_candidates(&folder, config)?;
+
+    let mut files = Vec::new();
+    let mut unmatched_files = Vec::new();
+    let mut used_rules = HashSet::new();
+    // Destinations claimed by earlier files in this preview
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+
+    for path in paths {
+        let decision = classify_and_route(&path, config);
+        if let Some(rule) = &decision.matched_rule {
+            used_rules.insert(rule.index);
+        } else {
+            unmatched_files.push(path.to_string_lossy().to_string());
+        }
+
+        let placements = match &decision.destination {
+            Some(destination) => {
+                let options = OrganizeOptions::from_decision(&decision);
+                plan_placements(&path, destination, &options, |p| claimed.contains(p))?
+            }
+            None => Vec::new(),
+        };
+        claimed.extend(placements.iter().filter_map(|p| p.path.as_ref().map(PathBuf::from)));
+        let final_path = placements.first().and_then(|p| p.path.clone());
+
+        files.push(PreviewEntry {
+            path: path.to_string_lossy().to_string(),
+            decision,
+            final_path,
+            placements,
+        });
+    }
+
+    let unused_rules = config
+        .rules
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !used_rules.contains(index))
+        .map(|(index, rule)| RuleMatch {
+            index,
+            name: rule.name.clone(),
+        })
+        .collect();
+
+    Ok(PreviewReport {
+        files,
+        unmatched_files,
+        unused_rules,
+    })
+}
@@ -0,0 +1,177 @@
+use crate::config::{Config, ConflictPolicy, DuplicatePolicy, RuleAction};
+use crate::file_type::effective_extension;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The rule that decided where a file goes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleMatch {
+    /// Position of the rule in `Config.rules`.
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+/// Where a file should go according to the configured rules, and why.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingDecision {
+    pub matched_rule: Option<RuleMatch>,
+    /// Set only when a rule matched and its destination could be resolved.
+    pub destination: Option<String>,
+    /// File name produced by the rule's rename template.
+    pub new_name: Option<String>,
+    /// How the file gets to `destination`; extra destinations are already expanded.
+    pub action: RuleAction,
+    /// What happens if identical content is already in the destination.
+    pub on_duplicate: DuplicatePolicy,
+    /// What happens if a different file already has the target name.
+    pub on_conflict: ConflictPolicy,
+    /// The extension the rules were evaluated against (sniffed when the name has none).
+    pub extension: String,
+    pub reason: String,
+}
+
+impl RoutingDecision {
+    pub fn is_routed(&self) -> bool {
+        self.destination.is_some()
+    }
+}
+
+/// Runs a file through the rule set without touching it.
+///
+/// This is the single place that decides where a file belongs; the watcher, the
+/// pending queue and manual moves all go through it so they can't disagree.
+pub fn classify_and_route(path: &Path, config: &Config) -> RoutingDecision {
+    let extension = effective_extension(path);
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("");
+    let created_date = path
+        .metadata()
+        .ok()
+        .and_then(|m| m.created().ok());
+    let path_buf = path.to_path_buf();
+
+    let Some((index, rule)) = config.find_matching_rule(&path_buf, &extension, file_name, created_date) else {
+        return RoutingDecision {
+            matched_rule: None,
+            destination: None,
+            new_name: None,
+            action: RuleAction::Move,
+            on_duplicate: DuplicatePolicy::KeepBoth,
+            on_conflict: config.conflict_policy,
+            reason: format!("No rule matches {}", file_name),
+            extension,
+        };
+    };
+
+    let matched_rule = Some(RuleMatch {
+        index,
+        name: rule.name.clone(),
+    });
+    let label = rule.name.clone().unwrap_or_else(|| format!("#{}", index + 1));
+
+    match config.resolve_rule(rule, &path_buf, file_name) {
+        Ok(resolved) => RoutingDecision {
+            matched_rule,
+            reason: format!("Rule {} matched", label),
+            destination: Some(resolved.destination),
+            new_name: resolved.new_name,
+            action: resolved.action,
+            on_duplicate: resolved.on_duplicate,
+            on_conflict: resolved.on_conflict,
+            extension,
+        },
+        Err(e) => RoutingDecision {
+            matched_rule,
+            destination: None,
+            new_name: None,
+            action: rule.action.clone(),
+            on_duplicate: rule.on_duplicate.clone(),
+            on_conflict: rule.on_conflict.unwrap_or(config.conflict_policy),
+            reason: format!("Rule {} matched but its destination is invalid: {}", label, e),
+            extension,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use serde_json::json;
+    use std::fs;
+
+    fn config(rules: serde_json::Value) -> Config {
+        serde_json::from_value(json!({ "rules": rules })).unwrap()
+    }
+
+    fn folder(name: &str) -> String {
+        std::env::temp_dir().join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn unmatched_file_has_no_destination() {
+        let config = config(json!([
+            { "condition": { "type": "filetype", "value": "pdf" }, "destination": folder("documents") }
+        ]));
+        let decision = classify_and_route(Path::new("/downloads/notes.txt"), &config);
+
+        assert_eq!(decision.matched_rule, None);
+        assert!(!decision.is_routed());
+        assert_eq!(decision.extension, "txt");
+        assert_eq!(decision.reason, "No rule matches notes.txt");
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config = config(json!([
+            { "condition": { "type": "filetype", "value": "jpg" }, "destination": folder("photos") },
+            { "name": "Invoices", "condition": { "type": "glob", "pattern": "invoice-*" }, "destination": folder("invoices") },
+            { "condition": { "type": "filetype", "value": "pdf" }, "destination": folder("documents") }
+        ]));
+        let decision = classify_and_route(Path::new("/downloads/invoice-42.pdf"), &config);
+
+        assert_eq!(
+            decision.matched_rule,
+            Some(RuleMatch {
+                index: 1,
+                name: Some("Invoices".to_string()),
+            })
+        );
+        assert_eq!(decision.destination, Some(folder("invoices")));
+        assert_eq!(decision.reason, "Rule Invoices matched");
+    }
+
+    #[test]
+    fn invalid_destination_template_is_reported() {
+        let config = config(json!([
+            { "condition": { "type": "filetype", "value": "pdf" }, "destination": folder("{bogus}") }
+        ]));
+        let decision = classify_and_route(Path::new("/downloads/report.pdf"), &config);
+
+        assert_eq!(decision.matched_rule.map(|m| m.index), Some(0));
+        assert_eq!(decision.destination, None);
+        assert!(
+            decision.reason.starts_with("Rule #1 matched but its destination is invalid:"),
+            "{}",
+            decision.reason
+        );
+    }
+
+    #[test]
+    fn extensionless_file_is_routed_by_sniffed_type() {
+        let dir = scratch_dir("routing-sniffed");
+        let file = dir.join("scan");
+        fs::write(&file, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let config = config(json!([
+            { "condition": { "type": "filetype", "value": "png" }, "destination": folder("images") }
+        ]));
+        let decision = classify_and_route(&file, &config);
+
+        assert_eq!(decision.extension, "png");
+        assert_eq!(decision.destination, Some(folder("images")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A file that has appeared but is not yet treated as complete.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlingFile {
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    pub since: u64,
+}
+
+impl SettlingFile {
+    pub fn new(path: &Path) -> Self {
+        SettlingFile {
+            path: path.to_string_lossy().to_string(),
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            size: path.metadata().map(|m| m.len()).unwrap_or(0),
+            since: crate::events::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SettleOptions {
+    /// How long size and modification time must stay the same.
+    pub quiet_period: Duration,
+    /// Give up on files that are still changing after this long.
+    pub max_wait: Duration,
+}
+
+impl SettleOptions {
+    pub fn from_config(config: &Config) -> Self {
+        SettleOptions {
+            quiet_period: Duration::from_millis(config.settle_quiet_period_ms),
+            max_wait: Duration::from_secs(config.settle_max_wait_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettleOutcome {
+    /// Size and modification time stayed the same for the quiet period. The file
+    /// is settled unless another process still has it open, which the caller
+    /// checks with `is_open_elsewhere` and reports back.
+    Quiet,
+    /// The file was removed or renamed while waiting.
+    Vanished,
+    TimedOut,
+}
+
+/// Follows one file until it settles, without blocking: call `poll` periodically.
+pub struct SettleTracker {
+    started: Instant,
+    quiet_since: Instant,
+    last: Option<(u64, Option<SystemTime>)>,
+    /// Waiting for the caller's open-file check.
+    checking: bool,
+}
+
+impl SettleTracker {
+    pub fn new(path: &Path) -> Self {
+        SettleTracker {
+            started: Instant::now(),
+            quiet_since: Instant::now(),
+            last: snapshot(path),
+            checking: false,
+        }
+    }
+
+    /// Restarts the quiet period, e.g. because another change event arrived.
+    pub fn touch(&mut self) {
+        self.quiet_since = Instant::now();
+    }
+
+    /// Stops reporting `Quiet` until `still_open`, while the caller checks whether
+    /// another process has the file open.
+    pub fn begin_open_check(&mut self) {
+        self.checking = true;
+    }
+
+    /// The file was open elsewhere, so it has to stay quiet for another period.
+    pub fn still_open(&mut self) {
+        self.checking = false;
+        self.touch();
+    }
+
+    /// Checks the file again. Returns an outcome once the file has kept the same
+    /// size and modification time for the quiet period, or once it has vanished
+    /// or the maximum wait has passed.
+    pub fn poll(&mut self, path: &Path, options: SettleOptions) -> Option<SettleOutcome> {
+        let Some(current) = snapshot(path) else {
+            return Some(SettleOutcome::Vanished);
+        };
+        if self.last != Some(current) {
+            self.last = Some(current);
+            self.quiet_since = Instant::now();
+        } else if !self.checking && self.quiet_since.elapsed() >= options.quiet_period {
+            return Some(SettleOutcome::Quiet);
+        }
+        if self.started.elapsed() >= options.max_wait {
+            return Some(SettleOutcome::TimedOut);
+        }
+        None
+    }
+}
+
+/// Whether a file that was already there can be handled without waiting: it
+/// hasn't been modified within the quiet period and nothing has it open.
+pub fn looks_settled(path: &Path, quiet_period: Duration) -> bool {
+    let quiet = path
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= quiet_period);
+    quiet && !is_open_elsewhere(path)
+}
+
+fn snapshot(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    let metadata = path.metadata().ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+/// Looks through other processes' open file descriptors. Processes of other
+/// users can't be inspected and are assumed not to have the file open.
+#[cfg(target_os = "linux")]
+pub fn is_open_elsewhere(path: &Path) -> bool {
+    let Ok(target) = path.canonicalize() else {
+        return false;
+    };
+    let own_pid = std::process::id().to_string();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    for process in processes.flatten() {
+        let pid = process.file_name();
+        let pid = pid.to_string_lossy();
+        if pid == own_pid || !pid.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        if fds
+            .flatten()
+            .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == target))
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Can take seconds, so it shouldn't run on a thread other files are waiting on.
+#[cfg(target_os = "macos")]
+pub fn is_open_elsewhere(path: &Path) -> bool {
+    // `lsof -t` prints the ids of processes holding the file and nothing otherwise
+    std::process::Command::new("lsof")
+        .arg("-t")
+        .arg("--")
+        .arg(path)
+        .output()
+        .map(|output| !output.stdout.is_empty())
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+pub fn is_open_elsewhere(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    // Opening without sharing fails while a writer still holds the file
+    match std::fs::OpenOptions::new().read(true).share_mode(0).open(path) {
+        Ok(_) => false,
+        Err(e) => e.raw_os_error() == Some(ERROR_SHARING_VIOLATION),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn is_open_elsewhere(_path: &Path) -> bool {
+    false
+}
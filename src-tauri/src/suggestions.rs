@@ -0,0 +1,152 @@
+use crate::config::Config;
+use crate::history::{self, HistoryAction, HistoryEntry, HistoryOutcome};
+use crate::routing::{classify_and_route, RuleMatch};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// At most this many destinations are suggested from past choices.
+const HISTORY_SUGGESTIONS: usize = 3;
+
+/// A destination the user can accept for a pending file with one click.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Suggestion {
+    pub destination: String,
+    /// File name produced by the rule's rename template.
+    #[serde(default)]
+    pub new_name: Option<String>,
+    /// Set when a rule routes the file to `destination`; accepting it applies the
+    /// rule's action and policies.
+    #[serde(default)]
+    pub rule: Option<RuleMatch>,
+    /// How many files with the same extension were organized into `destination`.
+    #[serde(default)]
+    pub uses: usize,
+}
+
+/// The rule that matches `path`, if any, and the destinations to offer for it:
+/// the rule's destination first, then the folders files with the same extension
+/// were most often moved or copied to.
+pub fn suggest(path: &Path, config: &Config) -> (Option<RuleMatch>, Vec<Suggestion>) {
+    let decision = classify_and_route(path, config);
+    let past = past_destinations(&decision.extension, &history::load_all());
+
+    let mut suggestions = Vec::new();
+    if let Some(destination) = &decision.destination {
+        suggestions.push(Suggestion {
+            destination: destination.clone(),
+            new_name: decision.new_name.clone(),
+            rule: decision.matched_rule.clone(),
+            uses: past.iter().find(|(d, _)| d == destination).map_or(0, |(_, uses)| *uses),
+        });
+    }
+    let current = path.parent().map(|p| p.to_string_lossy().to_string());
+    suggestions.extend(
+        past.into_iter()
+            .filter(|(destination, _)| {
+                Some(destination) != decision.destination.as_ref()
+                    && Some(destination) != current.as_ref()
+                    && Path::new(destination).is_dir()
+            })
+            .take(HISTORY_SUGGESTIONS)
+            .map(|(destination, uses)| Suggestion {
+                destination,
+                new_name: None,
+                rule: None,
+                uses,
+            }),
+    );
+    (decision.matched_rule, suggestions)
+}
+
+/// Folders that files with `extension` were successfully moved or copied to, most
+/// used first and most recently used among equals.
+fn past_destinations(extension: &str, entries: &[HistoryEntry]) -> Vec<(String, usize)> {
+    // Folder -> (uses, latest timestamp)
+    let mut folders: HashMap<String, (usize, u64)> = HashMap::new();
+    for entry in entries {
+        if !matches!(entry.action, HistoryAction::Moved | HistoryAction::Copied)
+            || entry.outcome != HistoryOutcome::Success
+        {
+            continue;
+        }
+        let same_extension = Path::new(&entry.source)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case(extension));
+        let folder = entry
+            .destination
+            .as_deref()
+            .and_then(|d| Path::new(d).parent())
+            .map(|p| p.to_string_lossy().to_string());
+        if let (true, Some(folder)) = (same_extension, folder) {
+            let used = folders.entry(folder).or_insert((0, 0));
+            used.0 += 1;
+            used.1 = used.1.max(entry.timestamp);
+        }
+    }
+
+    let mut folders: Vec<(String, (usize, u64))> = folders.into_iter().collect();
+    folders.sort_by_key(|(_, used)| Reverse(*used));
+    folders
+        .into_iter()
+        .map(|(folder, (uses, _))| (folder, uses))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(action: HistoryAction, source: &str, destination: &str, timestamp: u64) -> HistoryEntry {
+        let mut entry = HistoryEntry::new(action, source);
+        entry.destination = Some(destination.to_string());
+        entry.timestamp = timestamp;
+        entry
+    }
+
+    #[test]
+    fn ranks_by_uses_then_recency() {
+        let entries = vec![
+            entry(HistoryAction::Moved, "/in/a.pdf", "/docs/a.pdf", 1),
+            entry(HistoryAction::Copied, "/in/b.pdf", "/docs/b.pdf", 2),
+            entry(HistoryAction::Moved, "/in/c.pdf", "/old/c.pdf", 3),
+            entry(HistoryAction::Moved, "/in/d.pdf", "/recent/d.pdf", 4),
+        ];
+        assert_eq!(
+            past_destinations("pdf", &entries),
+            vec![
+                ("/docs".to_string(), 2),
+                ("/recent".to_string(), 1),
+                ("/old".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_extensions_case_insensitively() {
+        let entries = vec![
+            entry(HistoryAction::Moved, "/in/a.JPG", "/photos/a.JPG", 1),
+            entry(HistoryAction::Moved, "/in/b.jpg", "/photos/b.jpg", 2),
+            entry(HistoryAction::Moved, "/in/c.jpeg", "/other/c.jpeg", 3),
+            entry(HistoryAction::Moved, "/in/jpg", "/other/jpg", 4),
+        ];
+        assert_eq!(past_destinations("jpg", &entries), vec![("/photos".to_string(), 2)]);
+    }
+
+    #[test]
+    fn ignores_failures_links_and_other_actions() {
+        let mut failed = entry(HistoryAction::Moved, "/in/a.txt", "/failed/a.txt", 1);
+        failed.outcome = HistoryOutcome::Failed {
+            message: "Failed to move file".to_string(),
+        };
+        let entries = vec![
+            failed,
+            entry(HistoryAction::Symlinked, "/in/b.txt", "/links/b.txt", 2),
+            entry(HistoryAction::Reverted, "/notes/c.txt", "/in/c.txt", 3),
+            HistoryEntry::new(HistoryAction::Skipped, "/in/d.txt"),
+        ];
+        assert!(past_destinations("txt", &entries).is_empty());
+    }
+}
@@ -0,0 +1,479 @@
+use crate::patterns::sanitize_path_component;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Placeholders that are filled in from the file itself rather than from regex captures.
+pub const FILE_PLACEHOLDERS: &[&str] = &[
+    "ext",
+    "stem",
+    "slug",
+    "date",
+    "year",
+    "month",
+    "day",
+    "size_bucket",
+    "source_domain",
+];
+
+/// Filled in at move time with the first number that gives an unused file name.
+pub const COUNTER_PLACEHOLDER: &str = "counter";
+
+/// Values available to `{placeholder}` expansion for a single file.
+pub struct TemplateContext<'a> {
+    path: &'a Path,
+    captures: HashMap<String, String>,
+}
+
+impl<'a> TemplateContext<'a> {
+    pub fn new(path: &'a Path, captures: HashMap<String, String>) -> Self {
+        TemplateContext { path, captures }
+    }
+
+    /// Returns the raw (unsanitized) value for `name`. Regex captures take
+    /// precedence over file metadata so a rule can pull e.g. `{year}` from the name.
+    pub fn value(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.captures.get(name) {
+            return Some(value.clone());
+        }
+        match name {
+            "ext" => Some(
+                self.path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_lowercase())
+                    .unwrap_or_else(|| "other".to_string()),
+            ),
+            "stem" => self
+                .path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_string()),
+            "slug" => self
+                .path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(slugify),
+            "date" => self.timestamp().map(|t| t.format("%Y-%m-%d").to_string()),
+            "year" => self.timestamp().map(|t| t.format("%Y").to_string()),
+            "month" => self.timestamp().map(|t| t.format("%m").to_string()),
+            "day" => self.timestamp().map(|t| t.format("%d").to_string()),
+            "size_bucket" => self
+                .path
+                .metadata()
+                .ok()
+                .map(|m| size_bucket(m.len()).to_string()),
+            "source_domain" => Some(
+                source_url(self.path)
+                    .and_then(|url| domain_of(&url))
+                    .unwrap_or_else(|| "unknown".to_string()),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Creation time, or modification time where the filesystem has no creation time.
+    fn timestamp(&self) -> Option<DateTime<Local>> {
+        let metadata = self.path.metadata().ok()?;
+        metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .ok()
+            .map(DateTime::<Local>::from)
+    }
+}
+
+/// Lowercases and joins runs of letters and digits with `-`, e.g. "My Report (v2)" -> "my-report-v2".
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::new();
+    for c in value.chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+pub fn size_bucket(size: u64) -> &'static str {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    const GB: u64 = 1024 * MB;
+
+    if size < 100 * KB {
+        "tiny"
+    } else if size < 10 * MB {
+        "small"
+    } else if size < 100 * MB {
+        "medium"
+    } else if size < GB {
+        "large"
+    } else {
+        "huge"
+    }
+}
+
+/// Lists the `{name}` placeholders in a template, in order of appearance.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = fill_placeholders(template, |name| {
+        names.push(name.to_string());
+        Ok(String::new())
+    });
+    names
+}
+
+/// Replaces each `{name}` in a single pass, so substituted values are never re-expanded.
+pub fn fill_placeholders<F>(template: &str, mut value: F) -> Result<String, String>
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            break;
+        };
+        filled.push_str(&rest[..open]);
+        filled.push_str(&value(&after[..close])?);
+        rest = &after[close + 1..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+/// Expands `~`, `$VAR` and `${VAR}` in a path. Unset variables are left as written.
+pub fn expand_home_and_env(path: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut chars = path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&next) = chars.peek() {
+            if next.is_ascii_alphanumeric() || next == '_' {
+                name.push(next);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if braced && chars.next() != Some('}') {
+            return Err(format!("unclosed '${{' in '{}'", path));
+        }
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        match std::env::var(&name) {
+            Ok(value) => expanded.push_str(&value),
+            // Folder names may legitimately contain `$`, so keep unknown variables literally
+            Err(_) if braced => expanded.push_str(&format!("${{{}}}", name)),
+            Err(_) => expanded.push_str(&format!("${}", name)),
+        }
+    }
+
+    if expanded == "~" || expanded.starts_with("~/") {
+        let home = dirs::home_dir().ok_or_else(|| "Could not determine home directory".to_string())?;
+        expanded = format!("{}{}", home.to_string_lossy(), &expanded[1..]);
+    }
+    Ok(expanded)
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Expands a destination template for one file.
+///
+/// `~` and environment variables are expanded first; the directory they lead to
+/// before the first placeholder is the template's root, and the expanded path must
+/// stay inside it. When `allowed_roots` is non-empty the result must also be inside
+/// one of those.
+pub fn expand_destination(
+    template: &str,
+    context: &TemplateContext,
+    allowed_roots: &[String],
+) -> Result<String, String> {
+    let base = expand_home_and_env(template)?;
+
+    let expanded = fill_placeholders(&base, |name| {
+        context
+            .value(name)
+            .map(|value| sanitize_path_component(&value))
+            .ok_or_else(|| format!("unknown placeholder '{{{}}}' in '{}'", name, template))
+    })?;
+
+    let resolved = normalize_path(Path::new(&expanded));
+    if let Some(root) = template_root(&base) {
+        if !resolved.starts_with(&root) {
+            return Err(format!(
+                "destination '{}' resolves outside of '{}'",
+                template,
+                root.display()
+            ));
+        }
+    }
+    check_allowed_roots(&resolved, allowed_roots)?;
+
+    Ok(resolved.to_string_lossy().to_string())
+}
+
+/// Checks a template without a concrete file: every placeholder must be known and
+/// the fixed part must be absolute and inside the allowed roots.
+pub fn validate_destination(
+    template: &str,
+    capture_names: &[String],
+    allowed_roots: &[String],
+) -> Result<(), String> {
+    let base = expand_home_and_env(template)?;
+    for name in placeholders(&base) {
+        if !FILE_PLACEHOLDERS.contains(&name.as_str()) && !capture_names.contains(&name) {
+            return Err(format!("unknown placeholder '{{{}}}' in '{}'", name, template));
+        }
+    }
+    let root = template_root(&base).unwrap_or_else(|| normalize_path(Path::new(&base)));
+    if !placeholders(&base).is_empty() && !root.is_absolute() {
+        return Err(format!(
+            "destination '{}' must start with an absolute folder",
+            template
+        ));
+    }
+    check_allowed_roots(&root, allowed_roots)
+}
+
+/// Expands a rename template such as `{date}-{slug}.{ext}` into a file name.
+///
+/// `{counter}` is left in place for `organize_file_to_destination`, which fills it
+/// with the first number that doesn't collide with an existing file.
+pub fn expand_file_name(template: &str, context: &TemplateContext) -> Result<String, String> {
+    let name = fill_placeholders(template, |name| {
+        if name == COUNTER_PLACEHOLDER {
+            return Ok(format!("{{{}}}", COUNTER_PLACEHOLDER));
+        }
+        context
+            .value(name)
+            .map(|value| sanitize_path_component(&value))
+            .ok_or_else(|| format!("unknown placeholder '{{{}}}' in '{}'", name, template))
+    })?;
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') && name.trim_start_matches('.').is_empty() {
+        return Err(format!("rename template '{}' produced an empty name", template));
+    }
+    Ok(name.to_string())
+}
+
+pub fn validate_file_name(template: &str, capture_names: &[String]) -> Result<(), String> {
+    if template.contains(['/', '\\']) {
+        return Err(format!("rename template '{}' must not contain path separators", template));
+    }
+    for name in placeholders(template) {
+        if name != COUNTER_PLACEHOLDER
+            && !FILE_PLACEHOLDERS.contains(&name.as_str())
+            && !capture_names.contains(&name)
+        {
+            return Err(format!("unknown placeholder '{{{}}}' in '{}'", name, template));
+        }
+    }
+    Ok(())
+}
+
+/// The folder a destination template always resolves inside: the part before the
+/// first placeholder, or the whole destination when it has none.
+pub fn destination_root(template: &str) -> Option<PathBuf> {
+    let base = expand_home_and_env(template).ok()?;
+    Some(template_root(&base).unwrap_or_else(|| normalize_path(Path::new(&base))))
+}
+
+/// The fixed directory in front of the first placeholder, if the template has any.
+fn template_root(expanded: &str) -> Option<PathBuf> {
+    let open = expanded.find('{')?;
+    let prefix = &expanded[..open];
+    let root = match prefix.rfind(['/', std::path::MAIN_SEPARATOR]) {
+        Some(separator) => &prefix[..=separator],
+        None => "",
+    };
+    Some(normalize_path(Path::new(root)))
+}
+
+fn check_allowed_roots(path: &Path, allowed_roots: &[String]) -> Result<(), String> {
+    if allowed_roots.is_empty() {
+        return Ok(());
+    }
+    for root in allowed_roots {
+        let root = normalize_path(Path::new(&expand_home_and_env(root)?));
+        if path.starts_with(&root) {
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "destination '{}' is outside the allowed folders",
+        path.display()
+    ))
+}
+
+/// The URL a file was downloaded from, as recorded by the browser or OS.
+fn source_url(path: &Path) -> Option<String> {
+    #[cfg(unix)]
+    {
+        // macOS stores a binary plist of [download URL, referrer]; Linux browsers
+        // following the XDG convention store the plain URL.
+        for attribute in ["com.apple.metadata:kMDItemWhereFroms", "user.xdg.origin.url"] {
+            if let Ok(Some(bytes)) = xattr::get(path, attribute) {
+                if let Some(url) = find_url(&bytes) {
+                    return Some(url);
+                }
+            }
+        }
+        None
+    }
+    #[cfg(windows)]
+    {
+        let stream = format!("{}:Zone.Identifier", path.display());
+        let content = std::fs::read_to_string(stream).ok()?;
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix("HostUrl="))
+            .map(|url| url.trim().to_string())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(unix)]
+fn find_url(bytes: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(bytes);
+    let start = text.find("https://").or_else(|| text.find("http://"))?;
+    let url: String = text[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_graphic())
+        .collect();
+    Some(url)
+}
+
+fn domain_of(url: &str) -> Option<String> {
+    let after_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = after_scheme.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host).to_string();
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+// The expected paths are written Unix-style
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn context(captures: &[(&str, &str)]) -> TemplateContext<'static> {
+        let captures = captures
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        TemplateContext::new(Path::new("/downloads/report.pdf"), captures)
+    }
+
+    #[test]
+    fn parent_dir_cannot_escape_the_template_root() {
+        let context = context(&[("vendor", "acme")]);
+        assert_eq!(
+            expand_destination("/data/sorted/{vendor}/../{ext}", &context, &[]),
+            Ok("/data/sorted/pdf".to_string())
+        );
+        let escaped = expand_destination("/data/sorted/{vendor}/../../../etc", &context, &[]);
+        assert!(escaped.unwrap_err().contains("resolves outside of '/data/sorted'"));
+    }
+
+    #[test]
+    fn placeholder_values_stay_one_folder() {
+        let context = context(&[("vendor", "../../etc/passwd"), ("client", ".."), ("year", " / ")]);
+        assert_eq!(
+            expand_destination("/data/{vendor}", &context, &[]),
+            Ok("/data/..-..-etc-passwd".to_string())
+        );
+        assert_eq!(expand_destination("/data/{client}", &context, &[]), Ok("/data/_".to_string()));
+        assert_eq!(expand_destination("/data/{year}", &context, &[]), Ok("/data/-".to_string()));
+        assert_eq!(sanitize_path_component("a\\b\nc"), "a-b-c");
+    }
+
+    #[test]
+    fn home_and_environment_variables_are_expanded() {
+        let home = dirs::home_dir().unwrap().to_string_lossy().to_string();
+        assert_eq!(expand_home_and_env("~"), Ok(home.clone()));
+        assert_eq!(expand_home_and_env("~/Sorted"), Ok(format!("{}/Sorted", home)));
+        // Only a leading `~` means home
+        assert_eq!(expand_home_and_env("/data/~/x"), Ok("/data/~/x".to_string()));
+
+        std::env::set_var("FOLDER_WATCHER_TEMPLATE_TEST", "/srv/files");
+        assert_eq!(
+            expand_home_and_env("$FOLDER_WATCHER_TEMPLATE_TEST/a"),
+            Ok("/srv/files/a".to_string())
+        );
+        assert_eq!(
+            expand_home_and_env("${FOLDER_WATCHER_TEMPLATE_TEST}b"),
+            Ok("/srv/filesb".to_string())
+        );
+        assert_eq!(
+            expand_home_and_env("/data/$FOLDER_WATCHER_UNSET_VARIABLE/${FOLDER_WATCHER_UNSET_VARIABLE}/$"),
+            Ok("/data/$FOLDER_WATCHER_UNSET_VARIABLE/${FOLDER_WATCHER_UNSET_VARIABLE}/$".to_string())
+        );
+        assert!(expand_home_and_env("/data/${HOME").is_err());
+
+        let context = context(&[]);
+        assert_eq!(
+            expand_destination("$FOLDER_WATCHER_TEMPLATE_TEST/{ext}", &context, &[]),
+            Ok("/srv/files/pdf".to_string())
+        );
+    }
+
+    #[test]
+    fn destinations_must_be_inside_the_allowed_roots() {
+        let allowed = vec!["/data".to_string()];
+        let context = context(&[]);
+        assert_eq!(
+            expand_destination("/data/sorted/{ext}", &context, &allowed),
+            Ok("/data/sorted/pdf".to_string())
+        );
+        for template in ["/elsewhere/{ext}", "/data/../etc/{ext}", "/database/{ext}"] {
+            let error = expand_destination(template, &context, &allowed).unwrap_err();
+            assert!(error.contains("outside the allowed folders"), "{}: {}", template, error);
+        }
+
+        assert_eq!(validate_destination("/data/{vendor}/{ext}", &["vendor".to_string()], &allowed), Ok(()));
+        assert!(validate_destination("/elsewhere/{ext}", &[], &allowed).is_err());
+        assert!(validate_destination("/data/../etc", &[], &allowed).is_err());
+        assert!(validate_destination("/data/{vendor}", &[], &allowed)
+            .unwrap_err()
+            .contains("unknown placeholder"));
+        assert!(validate_destination("sorted/{ext}", &[], &[])
+            .unwrap_err()
+            .contains("must start with an absolute folder"));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::duplicates::content_hash;
+use crate::file_type::{self, FileKind};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Longest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Larger images aren't decoded; it would take too long and too much memory.
+const MAX_SOURCE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn thumbnail_dir() -> PathBuf {
+    let mut path = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("folder-watcher");
+    path.push("thumbnails");
+    path
+}
+
+/// Creates a PNG thumbnail for an image and returns its path. Thumbnails are named
+/// by content hash, so identical images share one. Returns `None` for anything
+/// that isn't an image the `image` crate can decode.
+pub fn create_thumbnail(path: &Path) -> Option<PathBuf> {
+    if file_type::detect(path)?.kind != FileKind::Image || path.metadata().ok()?.len() > MAX_SOURCE_SIZE {
+        return None;
+    }
+    let thumbnail = thumbnail_dir().join(format!("{}.png", content_hash(path).ok()?));
+    if thumbnail.is_file() {
+        return Some(thumbnail);
+    }
+
+    let image = image::open(path).ok()?;
+    fs::create_dir_all(thumbnail_dir()).ok()?;
+    // Written under a temporary name so a half-written thumbnail is never shown
+    let temp = thumbnail.with_extension("png.tmp");
+    let saved = image
+        .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .save_with_format(&temp, image::ImageFormat::Png)
+        .is_ok()
+        && fs::rename(&temp, &thumbnail).is_ok();
+    if !saved {
+        let _ = fs::remove_file(&temp);
+        return None;
+    }
+    Some(thumbnail)
+}
+
+/// Deletes a thumbnail unless it is one of `in_use`.
+pub fn discard_thumbnail(thumbnail: &str, in_use: &[&str]) {
+    let path = Path::new(thumbnail);
+    // Only ever delete files this module created
+    if !in_use.contains(&thumbnail) && path.parent() == Some(thumbnail_dir().as_path()) {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs::{self, File, FileTimes};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Called with the bytes copied so far and the total size while a file is copied
+/// to another volume.
+pub type Progress<'a> = &'a dyn Fn(u64, u64);
+
+/// Files smaller than this are copied without progress reports.
+const PROGRESS_MIN_SIZE: u64 = 16 * 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Renames `source` to `target`, falling back to copying and deleting when they
+/// are on different volumes.
+pub fn move_file(source: &Path, target: &Path, progress: Option<Progress>) -> Result<(), String> {
+    match fs::rename(source, target) {
+        Ok(()) => Ok(()),
+        Err(e) if is_cross_device(&e) => move_across_devices(source, target, progress),
+        Err(e) => Err(format!("Failed to move file: {}", e)),
+    }
+}
+
+/// Moves `source` to `target` without ever replacing anything already at
+/// `target`: if the name is taken, fails with `ErrorKind::AlreadyExists` and
+/// leaves both files alone. Uses a no-replace rename where the OS supports one,
+/// and otherwise claims the name with an empty placeholder file first.
+pub fn move_file_new(source: &Path, target: &Path, progress: Option<Progress>) -> io::Result<()> {
+    match rename_no_replace(source, target) {
+        Ok(()) => return Ok(()),
+        Err(e) if is_cross_device(&e) || is_unsupported(&e) => {}
+        Err(e) => return Err(e),
+    }
+
+    File::options().write(true).create_new(true).open(target)?;
+    let result = match fs::rename(source, target) {
+        Ok(()) => Ok(()),
+        Err(e) if is_cross_device(&e) => move_across_devices(source, target, progress).map_err(io::Error::other),
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        // Only the placeholder can be left at this point
+        let _ = fs::remove_file(target);
+    }
+    result
+}
+
+#[cfg(target_os = "linux")]
+fn rename_no_replace(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both arguments are valid NUL-terminated paths that outlive the call
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            source.as_ptr(),
+            libc::AT_FDCWD,
+            target.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn rename_no_replace(source: &Path, target: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let source = CString::new(source.as_os_str().as_bytes())?;
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    // SAFETY: both arguments are valid NUL-terminated paths that outlive the call
+    let result = unsafe { libc::renamex_np(source.as_ptr(), target.as_ptr(), libc::RENAME_EXCL) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn rename_no_replace(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Whether a no-replace rename failed because the kernel or filesystem lacks it.
+fn is_unsupported(error: &io::Error) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if error.raw_os_error() == Some(libc::EINVAL) {
+        return true;
+    }
+    error.kind() == io::ErrorKind::Unsupported
+}
+
+#[cfg(unix)]
+fn is_cross_device(error: &io::Error) -> bool {
+    // EXDEV on both Linux and macOS
+    error.raw_os_error() == Some(18)
+}
+
+#[cfg(windows)]
+fn is_cross_device(error: &io::Error) -> bool {
+    const ERROR_NOT_SAME_DEVICE: i32 = 17;
+    error.raw_os_error() == Some(ERROR_NOT_SAME_DEVICE)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_cross_device(_error: &io::Error) -> bool {
+    false
+}
+
+/// Copies into a hidden partial file next to `target`, syncs and verifies it,
+/// renames it into place and only then deletes `source`. On failure the source is
+/// left untouched and no partial copy remains.
+fn move_across_devices(source: &Path, target: &Path, progress: Option<Progress>) -> Result<(), String> {
+    let partial = partial_path(target)?;
+    if let Err(e) = copy_verified(source, &partial, progress) {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("Failed to copy file to the other volume: {}", e));
+    }
+    if let Err(e) = fs::rename(&partial, target) {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("Failed to move file: {}", e));
+    }
+    sync_parent(target);
+
+    if let Err(e) = fs::remove_file(source) {
+        // Keep the original rather than ending up with the file in two places
+        let _ = fs::remove_file(target);
+        return Err(format!("Copied file but failed to remove the original: {}", e));
+    }
+    Ok(())
+}
+
+fn partial_path(target: &Path) -> Result<PathBuf, String> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| "Invalid destination file name".to_string())?;
+    // Matches the default ignore patterns, so a watcher on the destination skips it
+    Ok(target.with_file_name(format!(".{}.partial", name.to_string_lossy())))
+}
+
+fn copy_verified(source: &Path, partial: &Path, progress: Option<Progress>) -> io::Result<()> {
+    let metadata = source.metadata()?;
+    let total = metadata.len();
+    let progress = progress.filter(|_| total >= PROGRESS_MIN_SIZE);
+
+    let mut reader = File::open(source)?;
+    let mut writer = File::create(partial)?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut copied = 0u64;
+    let mut last_report = Instant::now();
+    if let Some(progress) = progress {
+        progress(0, total);
+    }
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+        if let Some(progress) = progress {
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                progress(copied, total);
+                last_report = Instant::now();
+            }
+        }
+    }
+
+    fs::set_permissions(partial, metadata.permissions())?;
+    let mut times = FileTimes::new();
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    // Best effort: some filesystems don't support setting times
+    let _ = writer.set_times(times);
+    copy_xattrs(source, partial);
+    writer.sync_all()?;
+    drop(writer);
+
+    if !same_content(source, partial) {
+        return Err(io::Error::other("copy does not match the original"));
+    }
+    if let Some(progress) = progress {
+        progress(total, total);
+    }
+    Ok(())
+}
+
+/// Copies extended attributes, skipping any the destination filesystem rejects.
+#[cfg(unix)]
+fn copy_xattrs(source: &Path, target: &Path) {
+    let Ok(names) = xattr::list(source) else {
+        return;
+    };
+    for name in names {
+        if let Ok(Some(value)) = xattr::get(source, &name) {
+            let _ = xattr::set(target, &name, &value);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_xattrs(_source: &Path, _target: &Path) {}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) {
+    // Makes the rename durable before the original is deleted
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) {}
+
+/// Whether two files have identical contents.
+pub fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(a_meta), Ok(b_meta)) = (a.metadata(), b.metadata()) else {
+        return false;
+    };
+    if !b_meta.is_file() || a_meta.len() != b_meta.len() {
+        return false;
+    }
+    let (Ok(mut a), Ok(mut b)) = (File::open(a), File::open(b)) else {
+        return false;
+    };
+    let mut a_buf = vec![0u8; CHUNK_SIZE];
+    let mut b_buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let Ok(read) = a.read(&mut a_buf) else {
+            return false;
+        };
+        if read == 0 {
+            return true;
+        }
+        if b.read_exact(&mut b_buf[..read]).is_err() || a_buf[..read] != b_buf[..read] {
+            return false;
+        }
+    }
+}
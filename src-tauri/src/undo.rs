@@ -0,0 +1,182 @@
+use crate::events;
+use crate::config::RuleAction;
+use crate::file_organizer::{already_placed, place_unrecorded, ConflictResolution};
+use crate::history::{self, HistoryAction, HistoryEntry, HistoryOutcome};
+use crate::transfer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredFile {
+    /// Id of the history entry that was reverted.
+    pub id: u64,
+    pub from: String,
+    /// Where the file ended up; differs from the original path if that was taken.
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedRestore {
+    pub id: u64,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RevertReport {
+    pub restored: Vec<RestoredFile>,
+    pub failed: Vec<FailedRestore>,
+}
+
+/// Successful moves, copies and links that haven't been reverted yet, newest first.
+fn revertible_moves() -> Vec<HistoryEntry> {
+    let entries = history::load_all();
+    let reverted: HashSet<u64> = entries
+        .iter()
+        .filter(|e| e.action == HistoryAction::Reverted && e.outcome == HistoryOutcome::Success)
+        .filter_map(|e| e.reverts)
+        .collect();
+    entries
+        .into_iter()
+        .rev()
+        .filter(|e| {
+            matches!(
+                e.action,
+                HistoryAction::Moved | HistoryAction::Copied | HistoryAction::Symlinked | HistoryAction::Hardlinked
+            ) && e.outcome == HistoryOutcome::Success
+                && e.destination.is_some()
+                && !reverted.contains(&e.id)
+        })
+        .collect()
+}
+
+pub fn undo_last_move() -> Result<RevertReport, String> {
+    let last = revertible_moves()
+        .into_iter()
+        .next()
+        .ok_or_else(|| "There are no moves to undo".to_string())?;
+    Ok(revert_entries(vec![last]))
+}
+
+/// Reverts every move from the last `minutes` minutes and/or made by `rule`
+/// (a rule name, or its 1-based number for unnamed rules).
+pub fn revert_moves(minutes: Option<u64>, rule: Option<&str>) -> Result<RevertReport, String> {
+    if minutes.is_none() && rule.is_none() {
+        return Err("Choose a time window or a rule to revert".to_string());
+    }
+    let since = minutes.map(|m| events::now().saturating_sub(m * 60));
+    let entries = revertible_moves()
+        .into_iter()
+        .filter(|e| since.map_or(true, |since| e.timestamp >= since))
+        .filter(|e| rule.map_or(true, |rule| history::rule_matches(e, rule)))
+        .collect();
+    Ok(revert_entries(entries))
+}
+
+/// Moves each file back to where it came from, newest first so that a file moved
+/// twice ends up at its first location. Copies and links are removed instead, and
+/// a file that was overwritten is put back.
+fn revert_entries(entries: Vec<HistoryEntry>) -> RevertReport {
+    let mut report = RevertReport::default();
+    for entry in entries {
+        let current = entry.destination.clone().unwrap_or_default();
+        let mut revert = HistoryEntry::new(HistoryAction::Reverted, current.clone());
+        revert.reverts = Some(entry.id);
+        revert.rule = entry.rule.clone();
+        revert.size = entry.size;
+
+        let result = revert_entry(&entry, &current);
+        match result {
+            Ok(restored_to) => {
+                revert.destination = Some(restored_to.clone());
+                report.restored.push(RestoredFile {
+                    id: entry.id,
+                    from: current,
+                    to: restored_to,
+                });
+            }
+            Err(message) => {
+                revert.destination = Some(entry.source.clone());
+                revert.outcome = HistoryOutcome::Failed { message: message.clone() };
+                report.failed.push(FailedRestore {
+                    id: entry.id,
+                    path: current,
+                    message,
+                });
+            }
+        }
+        history::record(revert);
+    }
+    report
+}
+
+fn revert_entry(entry: &HistoryEntry, current: &str) -> Result<String, String> {
+    let overwritten = entry
+        .conflict
+        .as_ref()
+        .filter(|c| c.resolution == ConflictResolution::Overwritten);
+    let backup = match overwritten {
+        Some(conflict) => Some(
+            conflict
+                .backup
+                .clone()
+                .ok_or_else(|| "The file this replaced wasn't kept, so it can't be put back".to_string())?,
+        ),
+        None => None,
+    };
+
+    let restored_to = match entry.action {
+        // `current` was there before with the same content, so it stays
+        HistoryAction::Moved if entry.replaced_duplicate => restore_copy(current, &entry.source)?,
+        _ if entry.replaced_duplicate => entry.source.clone(),
+        HistoryAction::Moved => restore(current, &entry.source)?,
+        _ => remove_placed(&entry.source, current, entry.action).map(|_| entry.source.clone())?,
+    };
+    if let Some(backup) = backup {
+        transfer::move_file_new(Path::new(&backup), Path::new(current), None)
+            .map_err(|e| format!("Failed to put back the file it replaced: {}", e))?;
+    }
+    Ok(restored_to)
+}
+
+/// Removes a copy or link that a rule created, leaving the original untouched. A
+/// copy that no longer matches the original, or a link that no longer points at
+/// it, is left alone since it may hold the user's changes.
+fn remove_placed(source: &str, current: &str, action: HistoryAction) -> Result<(), String> {
+    let current_path = Path::new(current);
+    if current_path.symlink_metadata().is_err() {
+        return Err("File is no longer at its organized location".to_string());
+    }
+    let rule_action = match action {
+        HistoryAction::Symlinked => RuleAction::Symlink,
+        HistoryAction::Hardlinked => RuleAction::Hardlink,
+        _ => RuleAction::Copy,
+    };
+    if !already_placed(Path::new(source), current_path, &rule_action) {
+        return Err("File at the organized location has changed since it was placed".to_string());
+    }
+    fs::remove_file(current_path).map_err(|e| format!("Failed to remove file: {}", e))
+}
+
+/// Moves `current` back to `original`. If something else now lives at `original`,
+/// the restored file gets a ` (N)` suffix instead of replacing it.
+fn restore(current: &str, original: &str) -> Result<String, String> {
+    let current_path = Path::new(current);
+    if !current_path.is_file() {
+        return Err("File is no longer at its organized location".to_string());
+    }
+    place_unrecorded(current_path, Path::new(original), &RuleAction::Move)
+        .map(|restored| restored.to_string_lossy().to_string())
+}
+
+/// Puts a copy of `current` back at `original`, leaving `current` where it is.
+fn restore_copy(current: &str, original: &str) -> Result<String, String> {
+    let current_path = Path::new(current);
+    if !current_path.is_file() {
+        return Err("File is no longer at its organized location".to_string());
+    }
+    place_unrecorded(current_path, Path::new(original), &RuleAction::Copy)
+        .map(|restored| restored.to_string_lossy().to_string())
+}
@@ -0,0 +1,52 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of threads running submitted jobs in order of submission. A job
+/// that panics doesn't take its thread down; reporting it is up to the job.
+pub struct WorkerPool {
+    sender: mpsc::Sender<Job>,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..size.max(1) {
+            let receiver = receiver.clone();
+            std::thread::spawn(move || loop {
+                // The lock is released before the job runs so other workers can take the next one
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => {
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    // The pool was dropped
+                    Err(_) => break,
+                }
+            });
+        }
+
+        WorkerPool { sender }
+    }
+
+    pub fn submit<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let _ = self.sender.send(Box::new(job));
+    }
+}
+
+/// The message a panic was raised with, for reporting it.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+        .unwrap_or("unknown error")
+}